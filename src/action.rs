@@ -1,10 +1,221 @@
 //! General definition for an action the CLI-tool can do.
-use position_preserving_moodle_question_xml_edit::{QParser, Question};
+use position_preserving_moodle_question_xml_edit::{Change, QParser, Question};
+use position_preserving_moodle_question_xml_edit::stack::STACKPath;
+use crate::options::{OptionSpec, OptionValues};
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::fmt;
 
-pub trait Action {
-	/// Looks into a given question, returns some notes as well as a boolean
-	/// telling if something was done or would be done if the "write" flag was active.
-	fn process(&mut self, question: &Question, parser: &mut QParser, flags: Vec<String>) -> (bool, Vec<String>);
+/// Settings and circumstances actions may need while processing.
+#[derive(Debug, Clone)]
+pub struct Context {
+	/// Are the changes going to be written as they are. Only then may
+	/// actions do anything outside the question file, e.g. write files.
+	pub write: bool,
+	/// Are we showing what the changes would look like, as a diff, the
+	/// resulting XML or change by change for deciding on them.
+	pub diff: bool,
+	/// The file the questions come from, filled in by the runner when it
+	/// knows it.
+	pub file: Option<String>,
+	/// The files and directories given to process, for naming files
+	/// relative to them.
+	pub roots: Vec<String>
+}
+
+impl Context {
+	/// Should changes be registered with the parser. That is the case when
+	/// writing but also when we want to see what the result would look like.
+	/// Actions should still talk about "could do" unless actually writing.
+	pub fn changes_wanted(&self) -> bool {
+		self.write || self.diff
+	}
+}
+
+/// Turns a STACKPath to a short string for identifying the field a note
+/// is about, e.g. "prt1/0/truefeedback".
+pub fn stack_path_string(path: &STACKPath) -> String {
+	match path {
+		STACKPath::Root(field) => field.clone(),
+		STACKPath::Input(input, field) => format!("{input}/{field}"),
+		STACKPath::PRT(prt, field) => format!("{prt}/{field}"),
+		STACKPath::PRTNode(prt, node, field) => format!("{prt}/{node}/{field}"),
+		STACKPath::Test(test, field) => format!("test{test}/{field}"),
+		STACKPath::TestInput(test, input, field) => format!("test{test}/{input}/{field}"),
+		STACKPath::TestExpectation(test, prt, field) => format!("test{test}/{prt}/{field}")
+	}
+}
+
+/// How serious a note is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+	Info,
+	Warning,
+	Error
+}
+
+impl Severity {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Severity::Info => "info",
+			Severity::Warning => "warning",
+			Severity::Error => "error"
+		}
+	}
+
+	pub fn from_name(name: &str) -> Option<Severity> {
+		match name {
+			"info" => Some(Severity::Info),
+			"warning" => Some(Severity::Warning),
+			"error" => Some(Severity::Error),
+			_ => None
+		}
+	}
+}
+
+/// Something an action has to say about a question.
+#[derive(Debug, Clone)]
+pub struct Note {
+	pub severity: Severity,
+	/// Nesting for the textual output. 1 is a main finding of the action,
+	/// 2 and 3 are details of the previous ones and 0 is for raw output
+	/// that should be printed as is.
+	pub level: usize,
+	pub message: String,
+	/// The part of the question this is about, e.g. "questiontext",
+	/// "prt1/feedbackvariables" or the name of an attachment.
+	pub target: Option<String>,
+	/// Stable identifier of the sort of finding, e.g. "unmatched_reference",
+	/// for overriding severities and for baselines. Messages may change, so
+	/// every note should have one.
+	pub kind: Option<String>
+}
+
+impl Note {
+	pub fn info(message: String) -> Note {
+		Note {
+			severity: Severity::Info,
+			level: 1,
+			message,
+			target: None,
+			kind: None
+		}
+	}
+
+	pub fn warning(message: String) -> Note {
+		Note {
+			severity: Severity::Warning,
+			level: 1,
+			message,
+			target: None,
+			kind: None
+		}
+	}
+
+	pub fn error(message: String) -> Note {
+		Note {
+			severity: Severity::Error,
+			level: 1,
+			message,
+			target: None,
+			kind: None
+		}
+	}
+
+	/// Raw output, e.g. extracted content.
+	pub fn raw(message: String) -> Note {
+		Note {
+			severity: Severity::Info,
+			level: 0,
+			message,
+			target: None,
+			kind: None
+		}
+	}
+
+	pub fn at_level(mut self, level: usize) -> Note {
+		self.level = level;
+		self
+	}
+
+	pub fn about(mut self, target: String) -> Note {
+		self.target = Some(target);
+		self
+	}
+
+	pub fn of_kind(mut self, kind: &str) -> Note {
+		self.kind = Some(kind.to_string());
+		self
+	}
+}
+
+/// Named statistics collected by an action over the whole run.
+#[derive(Debug, Clone, Default)]
+pub struct Counters {
+	values: BTreeMap<String, usize>
+}
+
+impl Counters {
+	pub fn new() -> Counters {
+		Counters {
+			values: BTreeMap::new()
+		}
+	}
+
+	pub fn add(&mut self, name: &str, amount: usize) {
+		*self.values.entry(name.to_string()).or_insert(0) += amount;
+	}
+
+	/// Unknown counters are zero.
+	pub fn get(&self, name: &str) -> usize {
+		*self.values.get(name).unwrap_or(&0)
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = (&String, &usize)> {
+		self.values.iter()
+	}
+
+	/// Adds all the values of the other ones to these.
+	pub fn merge(&mut self, other: &Counters) {
+		for (name, value) in other.iter() {
+			self.add(name, *value);
+		}
+	}
+}
+
+/// Something prevented an action from handling a question. The question
+/// gets skipped but the run continues with the next one.
+#[derive(Debug, Clone)]
+pub struct ActionError {
+	pub message: String
+}
+
+impl ActionError {
+	pub fn new(message: String) -> ActionError {
+		ActionError {
+			message
+		}
+	}
+}
+
+impl fmt::Display for ActionError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.message)
+	}
+}
+
+/// Actions are `Send` as each worker thread gets its own instances, see
+/// `merge` for combining what they saw.
+pub trait Action: Send {
+	/// Looks into a given question, returns the changes it wants to make and
+	/// some notes. The changes are not registered with the parser, the runner
+	/// collects those of all actions and hands them over once the file is
+	/// done, so all actions see the content as it was loaded, unless they
+	/// `runs_after` other selected actions, whose changes are made first.
+	///
+	/// Should the question be too broken to handle, returns an error. In that
+	/// case the action must not have counted anything.
+	fn process(&mut self, question: &Question, parser: &mut QParser, context: &Context) -> Result<(Vec<Change>, Vec<Note>), ActionError>;
 
 	/// Gives a name for this action.
 	/// e.g. "Attachement checker"
@@ -19,7 +230,53 @@ pub trait Action {
 	/// Check if this action supports a given question type.
 	fn supports(&self, qtype: String) -> bool;
 
+	/// Options this action takes, given as `--<flag>-<name>`.
+	fn options(&self) -> Vec<OptionSpec> {
+		Vec::new()
+	}
+
+	/// Receives the values of the declared options, defaults filled in,
+	/// before any processing happens.
+	fn configure(&mut self, _options: &OptionValues) -> Result<(), String> {
+		Ok(())
+	}
+
+	/// Running order among actions with no dependencies between them,
+	/// lower runs first.
+	fn priority(&self) -> i32 {
+		0
+	}
+
+	/// Flags of actions that must run before this one on each question,
+	/// e.g. a check that should see the result of a rewrite.
+	fn runs_after(&self) -> Vec<String> {
+		Vec::new()
+	}
+
+	/// Groups of the changes the last call to `process` returned that only
+	/// work together, as indices to those changes, e.g. a `tans` pointing to
+	/// a variable added to the question variables. On review those are
+	/// decided on together and the rest one by one.
+	fn linked_changes(&self) -> Vec<Vec<usize>> {
+		Vec::new()
+	}
 
 	/// End report summarising what was or would have been done.
 	fn report(&self) -> Option<String>;
-}
\ No newline at end of file
+
+	/// The statistics behind the end report, for machine-readable output.
+	fn counters(&self) -> Counters {
+		Counters::new()
+	}
+
+	/// Takes in what another instance of this action, configured the same
+	/// way but run on other files, has seen. So that the end report covers
+	/// all files when they are processed in parallel.
+	fn merge(&mut self, _other: &dyn Action) {}
+
+	/// For `merge` to get at more than the counters of the other instance,
+	/// actions with such state return themselves.
+	fn as_any(&self) -> Option<&dyn Any> {
+		None
+	}
+}
@@ -0,0 +1,481 @@
+//! Moving attachments between the question file and a directory tree, so
+//! that they can be reviewed and edited with normal tools.
+//!
+//! The files of a question go to
+//! `<dir>/<file>/<question>/<field>/<path>/<name>`, where the file is the
+//! question file without its extension relative to the directory it was
+//! found in, the question is named by its name and the field is the path
+//! of the element the file is attached to with dots in place of slashes,
+//! e.g. `Algebra/q1/Q1/prt[prt1].node[0].truefeedback/image.png`, unnamed
+//! fields like answers being numbered, e.g. `answer[2]`. Should several
+//! questions of a file share a name, their index is added to it. Exporting
+//! writes the decoded files there and importing puts the content of those
+//! files back into the `<file>` elements they came from.
+//!
+//! Names are reduced to characters usable on any platform, so different
+//! ones may end up the same. Such files and questions are left alone and
+//! reported as errors.
+
+use position_preserving_moodle_question_xml_edit::{QParser, Question, ContentType, Change, ContentRef};
+use crate::action::{Action, ActionError, Context, Counters, Note};
+use crate::changes::{element_path, question_node};
+use crate::inputs::{relative_name, STDIN};
+use crate::options::{OptionKind, OptionSpec, OptionValues};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+const DEFAULT_DIR: &str = "attachments";
+
+/// A `<file>` of a question and where it goes on disk.
+pub struct QuestionFile {
+	/// Path and name, e.g. "/image.png".
+	pub name: String,
+	/// The field it is attached to, e.g. "prt[prt1]/node[0]/truefeedback".
+	pub field: String,
+	/// Relative to the directory of the question, e.g. "questiontext/image.png".
+	pub relative: PathBuf,
+	pub encoding: Option<String>,
+	/// The base64 content, none for an empty element.
+	pub content: Option<ContentRef>
+}
+
+/// A name usable as a single component of a path on any platform.
+fn safe_component(name: &str) -> String {
+	let safe: String = name.trim().chars()
+		.map(|c| if c.is_alphanumeric() || " -_.,[]()+".contains(c) { c } else { '_' })
+		.collect();
+	if safe.trim_matches('.').is_empty() {
+		"_".to_string()
+	} else {
+		safe
+	}
+}
+
+/// The directory of the file of the questions, e.g. "Algebra/q1".
+fn file_directory(context: &Context) -> PathBuf {
+	let mut directory: PathBuf = PathBuf::new();
+	match context.file.as_deref() {
+		Some(STDIN) | None => directory.push("stdin"),
+		Some(file) => {
+			let file: PathBuf = relative_name(file, &context.roots).with_extension("");
+			for part in file.components() {
+				if let Component::Normal(part) = part {
+					directory.push(safe_component(&part.to_string_lossy()));
+				}
+			}
+		}
+	}
+	directory
+}
+
+/// The directories of the questions of a file. Working them out needs all
+/// the questions, so it is done once per file.
+#[derive(Default)]
+pub struct QuestionDirectories {
+	/// The file they are for.
+	file: Option<String>,
+	/// The names of its questions, for telling when the file has changed.
+	names: Vec<String>,
+	/// None for those that would share a directory with another question.
+	directories: Vec<Option<PathBuf>>
+}
+
+impl QuestionDirectories {
+	pub fn new() -> QuestionDirectories {
+		QuestionDirectories::default()
+	}
+
+	/// The directory of the question, e.g. "Algebra/q1/Q1", or an error if
+	/// it cannot have one of its own.
+	pub fn of(&mut self, question: &Question, parser: &mut QParser, context: &Context) -> Result<PathBuf, String> {
+		let name: String = question.name.unwrap_cdata();
+		if self.file != context.file || self.names.get(question.index) != Some(&name) {
+			self.refresh(parser, context);
+		}
+		match self.directories.get(question.index) {
+			Some(Some(directory)) => Ok(directory.clone()),
+			_ => Err(format!("The question would share its directory with another question, rename one of them to export or import '{}'.", name))
+		}
+	}
+
+	fn refresh(&mut self, parser: &mut QParser, context: &Context) {
+		let file: PathBuf = file_directory(context);
+		self.file = context.file.clone();
+		self.names = parser.find_questions().iter().map(|q| q.name.unwrap_cdata()).collect();
+		let safe: Vec<String> = self.names.iter().map(|n| safe_component(n)).collect();
+		// Compared as a case-insensitive file system would.
+		let mut uses: HashMap<String, usize> = HashMap::new();
+		for name in &safe {
+			*uses.entry(name.to_lowercase()).or_default() += 1;
+		}
+		let named: Vec<String> = safe.iter().enumerate()
+			.map(|(index, name)| if uses[&name.to_lowercase()] > 1 { format!("{}-{}", name, index + 1) } else { name.clone() })
+			.collect();
+		// A name ending like the index of another may still clash.
+		let mut taken: HashMap<String, usize> = HashMap::new();
+		for name in &named {
+			*taken.entry(name.to_lowercase()).or_default() += 1;
+		}
+		self.directories = named.into_iter()
+			.map(|name| Some(file.join(&name)).filter(|_| taken[&name.to_lowercase()] == 1))
+			.collect();
+	}
+}
+
+/// The files of the question.
+pub fn question_files(question: &Question, parser: &mut QParser) -> Result<Vec<QuestionFile>, ActionError> {
+	let file_elements = parser.get_elements(question.index, vec!["file".to_string()]);
+	let content: String = parser.get_current_content();
+	let options = roxmltree::ParsingOptions {
+		allow_dtd: true,
+		..roxmltree::ParsingOptions::default()
+	};
+	let doc = roxmltree::Document::parse_with_options(&content, options)
+		.map_err(|e| ActionError::new(format!("Could not parse the document: {}", e)))?;
+
+	// The parser lists the elements in document order.
+	let file_nodes: Vec<roxmltree::Node> = question_node(&doc, question.index)
+		.map(|q| q.descendants().filter(|n| n.has_tag_name("file")).collect())
+		.unwrap_or_default();
+	if file_nodes.len() != file_elements.len() {
+		return Err(ActionError::new("The files of the question do not add up.".to_string()));
+	}
+
+	let mut files: Vec<QuestionFile> = Vec::new();
+	for (file_element, file_node) in file_elements.iter().zip(file_nodes) {
+		if !matches!(file_element, ContentType::Element(..)) {
+			return Err(ActionError::new("Unexpected ContentType received as a search result.".to_string()));
+		}
+		let path: String = file_element.clone().get_attr("path".to_string())
+			.ok_or_else(|| ActionError::new("File element without a 'path' attribute.".to_string()))?.basic_entity_decode();
+		let name: String = file_element.clone().get_attr("name".to_string())
+			.ok_or_else(|| ActionError::new("File element without a 'name' attribute.".to_string()))?.basic_entity_decode();
+		let encoding: Option<String> = file_element.clone().get_attr("encoding".to_string()).map(|e| e.basic_entity_decode());
+		let field: String = file_node.parent_element()
+			.map(element_path)
+			.filter(|p| !p.is_empty())
+			.unwrap_or("question".to_string());
+
+		let mut relative: PathBuf = PathBuf::from(safe_component(&field.replace('/', ".")));
+		for part in path.split('/').filter(|p| !p.is_empty()) {
+			relative.push(safe_component(part));
+		}
+		relative.push(safe_component(&name));
+		files.push(QuestionFile {
+			name: format!("{path}{name}"),
+			field,
+			relative,
+			encoding,
+			content: file_element.clone().get_content()
+		});
+	}
+	Ok(files)
+}
+
+/// The files that would end up in the same place on disk, in groups, as
+/// on a case-insensitive file system.
+pub fn collisions(files: &[QuestionFile]) -> Vec<Vec<&QuestionFile>> {
+	let mut by_place: HashMap<String, Vec<&QuestionFile>> = HashMap::new();
+	for file in files {
+		by_place.entry(file.relative.to_string_lossy().to_lowercase()).or_default().push(file);
+	}
+	let mut result: Vec<Vec<&QuestionFile>> = by_place.into_values().filter(|group| group.len() > 1).collect();
+	result.sort_by(|a, b| a[0].relative.cmp(&b[0].relative));
+	result
+}
+
+/// Tells about files that cannot have a place of their own.
+fn collision_notes(collisions: &[Vec<&QuestionFile>], verb: &str) -> Vec<Note> {
+	collisions.iter().map(|group| {
+		let names: Vec<String> = group.iter().map(|f| format!("'{}'", f.name)).collect();
+		Note::error(format!("Not {} {}, they would all be '{}'.", verb, names.join(" and "), group[0].relative.display()))
+			.about(group[0].name.clone()).of_kind("colliding_files")
+	}).collect()
+}
+
+/// The decoded content of a file, if it is base64 encoded and decodes.
+pub fn decoded(file: &QuestionFile) -> Result<Vec<u8>, String> {
+	if file.encoding.as_deref() != Some("base64") {
+		return Err(format!("'{}' is not base64 encoded.", file.name));
+	}
+	let encoded: String = file.content.as_ref().map(|c| c.content.split_whitespace().collect()).unwrap_or_default();
+	BASE64.decode(encoded).map_err(|e| format!("'{}' does not decode: {}", file.name, e))
+}
+
+pub struct FileExporter {
+	// "exported_files" and "exported_bytes" for those written or that would
+	// be and "unchanged_files" for those already there as they are.
+	counters: Counters,
+	dir: String,
+	// Were the files written or just looked at.
+	write: bool,
+	directories: QuestionDirectories
+}
+
+impl Default for FileExporter {
+	fn default() -> Self {
+		FileExporter::new()
+	}
+}
+
+impl FileExporter {
+	/// Simple initialisation logic.
+	pub fn new() -> FileExporter {
+		FileExporter {
+			counters: Counters::new(),
+			dir: DEFAULT_DIR.to_string(),
+			write: false,
+			directories: QuestionDirectories::new()
+		}
+	}
+}
+
+impl Action for FileExporter {
+	fn process(&mut self, question: &Question, parser: &mut QParser, context: &Context) -> Result<(Vec<Change>, Vec<Note>), ActionError> {
+		let mut notes: Vec<Note> = Vec::new();
+		let mut counters: Counters = Counters::new();
+		self.write = context.write;
+		let directory: PathBuf = match self.directories.of(question, parser, context) {
+			Ok(directory) => directory,
+			Err(message) => return Ok((Vec::new(), vec![Note::error(message).of_kind("colliding_questions")]))
+		};
+		let files: Vec<QuestionFile> = question_files(question, parser)?;
+		let collisions: Vec<Vec<&QuestionFile>> = collisions(&files);
+		notes.extend(collision_notes(&collisions, "exporting"));
+
+		for file in files.iter().filter(|f| !collisions.iter().flatten().any(|c| std::ptr::eq(*c, *f))) {
+			let data: Vec<u8> = match decoded(file) {
+				Ok(data) => data,
+				Err(message) => {
+					notes.push(Note::warning(format!("Not exporting {}", message)).about(file.name.clone()).of_kind("unexportable_file"));
+					continue;
+				}
+			};
+			let target: PathBuf = Path::new(&self.dir).join(&directory).join(&file.relative);
+			if fs::read(&target).is_ok_and(|present| present == data) {
+				counters.add("unchanged_files", 1);
+				continue;
+			}
+			if context.write {
+				let written = target.parent().map(fs::create_dir_all).unwrap_or(Ok(()))
+					.and_then(|_| fs::write(&target, &data));
+				if let Err(e) = written {
+					notes.push(Note::error(format!("Could not export '{}' to '{}': {}", file.name, target.display(), e)).about(file.name.clone()).of_kind("export_failed"));
+					continue;
+				}
+				notes.push(Note::info(format!("Exported '{}' to '{}', {} bytes.", file.name, target.display(), data.len())).about(file.name.clone()).of_kind("exported_file"));
+			} else {
+				notes.push(Note::info(format!("Could export '{}' to '{}', {} bytes.", file.name, target.display(), data.len())).about(file.name.clone()).of_kind("exported_file"));
+			}
+			counters.add("exported_files", 1);
+			counters.add("exported_bytes", data.len());
+		}
+
+		self.counters.merge(&counters);
+		Ok((Vec::new(), notes))
+	}
+
+	fn name(&self) -> String {
+		"Attachment exporter".to_string()
+	}
+
+	fn flag(&self) -> String {
+		"exportfiles".to_string()
+	}
+
+	fn description(&self) -> String {
+		"Writes the attachments of questions to a directory tree, one directory per
+question file, question and field, e.g. attachments/bank/Q1/questiontext/a.png
+for bank.xml, so that they can be reviewed and edited with normal tools.
+Files already there as they are are left alone. Nothing is written without
+--write.
+
+Use --importfiles to bring the edited files back into the questions.".to_string()
+	}
+
+	fn options(&self) -> Vec<OptionSpec> {
+		vec![
+			OptionSpec::new("dir", OptionKind::Text, "The directory to export to.").default(DEFAULT_DIR)
+		]
+	}
+
+	fn configure(&mut self, options: &OptionValues) -> Result<(), String> {
+		self.dir = options.text("dir").unwrap_or(DEFAULT_DIR.to_string());
+		Ok(())
+	}
+
+	fn supports(&self, _qtype: String) -> bool {
+		true
+	}
+
+	fn report(&self) -> Option<String> {
+		let exported: usize = self.counters.get("exported_files");
+		if exported == 0 && self.counters.get("unchanged_files") == 0 {
+			return None;
+		}
+		let verb: &str = if self.write { "Exported" } else { "Could export" };
+		Some(format!("{} {} files, {} bytes, to '{}', {} files were there already.",
+			verb, exported, self.counters.get("exported_bytes"), self.dir, self.counters.get("unchanged_files")))
+	}
+
+	fn counters(&self) -> Counters {
+		self.counters.clone()
+	}
+
+	fn merge(&mut self, other: &dyn Action) {
+		self.counters.merge(&other.counters());
+	}
+}
+
+pub struct FileImporter {
+	// "imported_files" and "imported_bytes" for those changed or that would
+	// be, "unknown_files" for those on disk with no `<file>` to go to and
+	// "missing_files" for those of questions not found on disk.
+	counters: Counters,
+	dir: String,
+	// Were the changes written or just looked at.
+	write: bool,
+	directories: QuestionDirectories
+}
+
+impl Default for FileImporter {
+	fn default() -> Self {
+		FileImporter::new()
+	}
+}
+
+impl FileImporter {
+	/// Simple initialisation logic.
+	pub fn new() -> FileImporter {
+		FileImporter {
+			counters: Counters::new(),
+			dir: DEFAULT_DIR.to_string(),
+			write: false,
+			directories: QuestionDirectories::new()
+		}
+	}
+}
+
+impl Action for FileImporter {
+	fn process(&mut self, question: &Question, parser: &mut QParser, context: &Context) -> Result<(Vec<Change>, Vec<Note>), ActionError> {
+		let mut notes: Vec<Note> = Vec::new();
+		let mut changes: Vec<Change> = Vec::new();
+		let mut counters: Counters = Counters::new();
+		self.write = context.write;
+		let directory: PathBuf = match self.directories.of(question, parser, context) {
+			Ok(directory) => directory,
+			Err(message) => return Ok((Vec::new(), vec![Note::error(message).of_kind("colliding_questions")]))
+		};
+		let files: Vec<QuestionFile> = question_files(question, parser)?;
+		let root: PathBuf = Path::new(&self.dir).join(&directory);
+		if !files.is_empty() && !root.is_dir() {
+			notes.push(Note::warning(format!("Nothing to import, '{}' is not there.", root.display())).of_kind("missing_directory"));
+			counters.add("missing_files", files.len());
+			self.counters.merge(&counters);
+			return Ok((changes, notes));
+		}
+		let collisions: Vec<Vec<&QuestionFile>> = collisions(&files);
+		notes.extend(collision_notes(&collisions, "importing"));
+
+		for file in files.iter().filter(|f| !collisions.iter().flatten().any(|c| std::ptr::eq(*c, *f))) {
+			let source: PathBuf = root.join(&file.relative);
+			// Left as they are, but it may be a file renamed by mistake.
+			let data: Vec<u8> = match fs::read(&source) {
+				Ok(data) => data,
+				Err(_) => {
+					notes.push(Note::warning(format!("Not importing '{}', '{}' is not there.", file.name, source.display())).about(file.name.clone()).of_kind("missing_file"));
+					counters.add("missing_files", 1);
+					continue;
+				}
+			};
+			if decoded(file).is_ok_and(|current| current == data) {
+				continue;
+			}
+			let content: &ContentRef = match (&file.content, file.encoding.as_deref()) {
+				(Some(content), Some("base64")) => content,
+				_ => {
+					notes.push(Note::warning(format!("Not importing '{}', the file in the question is not base64 encoded content.", source.display())).about(file.name.clone()).of_kind("unimportable_file"));
+					continue;
+				}
+			};
+			if context.write {
+				notes.push(Note::info(format!("Importing '{}' from '{}', {} bytes.", file.name, source.display(), data.len())).about(file.name.clone()).of_kind("imported_file"));
+			} else {
+				notes.push(Note::info(format!("Could import '{}' from '{}', {} bytes.", file.name, source.display(), data.len())).about(file.name.clone()).of_kind("imported_file"));
+			}
+			changes.push(Change::new(content.clone(), BASE64.encode(&data)));
+			counters.add("imported_files", 1);
+			counters.add("imported_bytes", data.len());
+		}
+
+		// New files have no field or encoding to go with, so they need to be
+		// attached in Moodle.
+		let known: HashSet<PathBuf> = files.iter().map(|f| root.join(&f.relative)).collect();
+		for entry in walkdir::WalkDir::new(&root).into_iter().filter_map(|e| e.ok()).filter(|e| e.file_type().is_file()) {
+			if !known.contains(entry.path()) {
+				notes.push(Note::warning(format!("Not importing '{}', the question has no such file.", entry.path().display())).of_kind("unknown_file"));
+				counters.add("unknown_files", 1);
+			}
+		}
+
+		self.counters.merge(&counters);
+		Ok((changes, notes))
+	}
+
+	fn name(&self) -> String {
+		"Attachment importer".to_string()
+	}
+
+	fn flag(&self) -> String {
+		"importfiles".to_string()
+	}
+
+	fn description(&self) -> String {
+		"Puts the files exported with --exportfiles back into the questions, after
+they have been edited. Only files that differ from those in the question are
+changed, files without a counterpart in the question are reported but not
+added and those missing are reported and left as they are.".to_string()
+	}
+
+	fn options(&self) -> Vec<OptionSpec> {
+		vec![
+			OptionSpec::new("dir", OptionKind::Text, "The directory to import from.").default(DEFAULT_DIR)
+		]
+	}
+
+	fn configure(&mut self, options: &OptionValues) -> Result<(), String> {
+		self.dir = options.text("dir").unwrap_or(DEFAULT_DIR.to_string());
+		Ok(())
+	}
+
+	fn supports(&self, _qtype: String) -> bool {
+		true
+	}
+
+	fn report(&self) -> Option<String> {
+		let imported: usize = self.counters.get("imported_files");
+		if imported == 0 && self.counters.get("unknown_files") == 0 && self.counters.get("missing_files") == 0 {
+			return None;
+		}
+		let verb: &str = if self.write { "Imported" } else { "Could import" };
+		let mut result: String = format!("{} {} files, {} bytes, from '{}'.", verb, imported, self.counters.get("imported_bytes"), self.dir);
+		if self.counters.get("unknown_files") > 0 {
+			result.push_str(&format!("\n{} files had no counterpart in the questions and were left out.", self.counters.get("unknown_files")));
+		}
+		if self.counters.get("missing_files") > 0 {
+			result.push_str(&format!("\n{} files of the questions were not found and were left as they are.", self.counters.get("missing_files")));
+		}
+		Some(result)
+	}
+
+	fn counters(&self) -> Counters {
+		self.counters.clone()
+	}
+
+	fn merge(&mut self, other: &dyn Action) {
+		self.counters.merge(&other.counters());
+	}
+}
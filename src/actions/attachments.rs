@@ -1,135 +1,512 @@
 //! Logic for detecting unused attachment files present in questions.
 //! If executed with "write"-flag will remove unused ones.
+//!
+//! Moodle keeps the files of each field in a separate filearea, so a
+//! reference only finds files attached to the same field. The fields are
+//! the elements with a `<text>` or `<file>` inside, e.g. `questiontext`,
+//! `generalfeedback`, the feedbacks of PRT nodes, hints and answers.
+//! Fields holding Maxima, e.g. `questionvariables`, have no filearea of
+//! their own; references in their strings end up in some text and may use
+//! the files of any field.
 
 use position_preserving_moodle_question_xml_edit::{QParser, Question, ContentType, Change, ContentRef};
-use crate::action::Action;
-use regex::Regex;
-use std::collections::HashMap;
-use std::collections::HashSet;
-use urlencoding::decode as url_decode;
+use crate::action::{Action, ActionError, Context, Counters, Note};
+use crate::changes::{element_path, question_node};
+use crate::options::{OptionKind, OptionSpec, OptionValues};
+use crate::pluginfile;
+use std::collections::{BTreeMap, HashMap};
 
 pub struct FileAttachmentChecker {
-	total_files: usize,
-	total_bytes: usize,
-	removed_files: usize,
-	removed_bytes: usize,
-	all_ok: bool
+	// "total_files", "total_bytes", "removable_files", "removable_bytes",
+	// "cross_area_references", "moved_files", "missing_references",
+	// "rewritten_references" and "unmatched_questions" for those where we
+	// could not match all references.
+	counters: Counters,
+	// Move files referenced from another field to that field.
+	move_files: bool,
+	// Point references to missing files to the one similar file present.
+	fix_missing: bool,
+	// The changes moving files in the last question, which only work
+	// together as the originals go once the copies are in place.
+	linked: Vec<Vec<usize>>
+}
+
+/// A field with its own filearea.
+struct Area {
+	/// Path of the field in the question, e.g. "prt[prt1]/node[0]/truefeedback".
+	name: String,
+	/// Does Moodle keep files for it, i.e. is it not a field of Maxima.
+	filearea: bool,
+	/// References from the field, raw and decoded.
+	references: Vec<(String, String)>,
+	files: Vec<AreaFile>,
+	/// The `<text>` element of the field, if any, for placing moved files.
+	text: Option<ContentRef>,
+	/// Where the `<text>` element starts in the document.
+	text_start: usize
+}
+
+struct AreaFile {
+	/// Path and name, e.g. "/image.png".
+	name: String,
+	whole_element: ContentRef,
+	/// Where the element starts in the document.
+	start: usize,
+	/// Referenced from its own field.
+	used: bool,
+	/// Fields that reference it but do not have it.
+	wanted_by: Vec<usize>
+}
+
+impl Default for FileAttachmentChecker {
+	fn default() -> Self {
+		FileAttachmentChecker::new()
+	}
 }
 
 impl FileAttachmentChecker {
 	/// Simple initialisation logic.
 	pub fn new() -> FileAttachmentChecker {
 		FileAttachmentChecker {
-			total_files: 0,
-			total_bytes: 0,
-			removed_files: 0,
-			removed_bytes: 0,
-			all_ok: true
+			counters: Counters::new(),
+			move_files: false,
+			fix_missing: false,
+			linked: Vec::new()
+		}
+	}
+
+	/// Reports a reference to a missing file along with the files present
+	/// resembling it. With `fix_missing` rewrites the reference to the file
+	/// resembling it most, if there is one clear choice Moodle would find
+	/// that only differs in how the name is written.
+	/// Tells whether it was rewritten.
+	fn report_missing(&self, areas: &mut [Area], area: usize, (raw, decoded): (&str, &str), rewrites: &mut BTreeMap<usize, String>, write: bool, notes: &mut Vec<Note>) -> bool {
+		// How and the field and index of the files.
+		let mut similar: Vec<(Resemblance, usize, usize)> = Vec::new();
+		for (other, a) in areas.iter().enumerate() {
+			for (i, file) in a.files.iter().enumerate() {
+				if let Some(resemblance) = resemblance(decoded, &file.name) {
+					similar.push((resemblance, other, i));
+				}
+			}
+		}
+		similar.sort();
+		let findable = |other: usize| other == area || !areas[area].filearea;
+
+		let mut message: String = format!("References missing file '{raw}' from '{}'.", areas[area].name);
+		if !similar.is_empty() {
+			let listed: Vec<String> = similar.iter().map(|(resemblance, other, i)| {
+				let name: &str = &areas[*other].files[*i].name;
+				if findable(*other) {
+					format!("'{}' ({})", name, resemblance.describe())
+				} else {
+					format!("'{}' ({}, attached to '{}')", name, resemblance.describe(), areas[*other].name)
+				}
+			}).collect();
+			message.push_str(&format!(" Similar files present: {}.", listed.join(", ")));
+		}
+		let warning: usize = notes.len();
+		notes.push(Note::warning(message).about(raw.to_string()).of_kind("unmatched_reference"));
+		if !self.fix_missing {
+			return false;
+		}
+
+		let candidates: Vec<(Resemblance, usize, usize)> = similar.into_iter()
+			.filter(|(resemblance, other, _)| resemblance.same_file() && findable(*other))
+			.collect();
+		let (other, i) = match candidates.as_slice() {
+			[] => return false,
+			[(_, other, i)] => (*other, *i),
+			[(first, other, i), (second, _, _), ..] if first < second => (*other, *i),
+			_ => {
+				notes.push(Note::info(format!("Not rewriting '{raw}', several files resemble it as much.")).about(raw.to_string()).of_kind("rewritten_reference"));
+				return false;
+			}
+		};
+		let current: String = match (rewrites.get(&area), &areas[area].text) {
+			(Some(rewritten), _) => rewritten.clone(),
+			(None, Some(text)) => text.content.clone(),
+			(None, None) => return false
+		};
+		// Keeping the query and fragment, if any.
+		let suffix: &str = raw.find(['?', '#']).map(|p| &raw[p..]).unwrap_or_default();
+		let name: String = areas[other].files[i].name.clone();
+		let encoded: String = name.split('/').map(|part| urlencoding::encode(part).to_string()).collect::<Vec<String>>().join("/");
+		let new_content: String = replace_reference(&current, raw, &format!("{encoded}{suffix}"));
+		if new_content == current {
+			// Escaped differently in the XML than in the text.
+			notes.push(Note::info(format!("Not rewriting '{raw}', could not find it in the text of '{}'.", areas[area].name)).about(raw.to_string()).of_kind("rewritten_reference"));
+			return false;
+		}
+		rewrites.insert(area, new_content);
+		areas[other].files[i].used = true;
+		if write {
+			// Nothing to warn about once fixed.
+			notes.remove(warning);
+			notes.push(Note::info(format!("Rewriting reference '{raw}' to '{encoded}{suffix}'.")).about(raw.to_string()).of_kind("rewritten_reference"));
+		} else {
+			notes.push(Note::info(format!("Could rewrite reference '{raw}' to '{encoded}{suffix}'.")).about(raw.to_string()).of_kind("rewritten_reference"));
+		}
+		true
+	}
+
+	/// Attaches the files referenced from other fields to those fields. The
+	/// original is removed unless its own field uses it too. To leave the
+	/// text of the field as it is, the files go after the last file staying
+	/// in the field or take the place of one leaving it. Only a field
+	/// without files gets them after its `<text>`, in which case the text
+	/// includes its rewrites, which are taken out of `rewrites`.
+	fn move_misplaced(&self, areas: &[Area], content: &str, rewrites: &mut BTreeMap<usize, String>, write: bool, notes: &mut Vec<Note>, counters: &mut Counters) -> Vec<Change> {
+		let leaving = |f: &AreaFile| !f.used && !f.wanted_by.is_empty();
+		let mut changes: Vec<Change> = Vec::new();
+		// The leaving files replaced by incoming ones, by their start.
+		let mut replaced: Vec<usize> = Vec::new();
+		for (target_index, target) in areas.iter().enumerate() {
+			let incoming: Vec<(&Area, &AreaFile)> = areas.iter()
+				.flat_map(|a| a.files.iter().map(move |f| (a, f)))
+				.filter(|(_, f)| f.wanted_by.contains(&target_index))
+				.collect();
+			if incoming.is_empty() {
+				continue;
+			}
+			let staying: Option<&AreaFile> = target.files.iter().rev().find(|f| f.used);
+			let going: Option<&AreaFile> = target.files.iter().rev().find(|f| leaving(f));
+			let (anchor, anchor_start, mut new_content): (&ContentRef, usize, String) = match (staying, going, &target.text) {
+				(Some(file), _, _) => (&file.whole_element, file.start, file.whole_element.content.clone()),
+				(None, Some(file), _) => {
+					replaced.push(file.start);
+					(&file.whole_element, file.start, String::new())
+				},
+				(None, None, Some(text)) => (text, target.text_start, rewrites.remove(&target_index).unwrap_or(text.content.clone())),
+				(None, None, None) => {
+					notes.push(Note::warning(format!("No place to attach files to '{}', not moving them.", target.name)).of_kind("unmovable_file"));
+					continue;
+				}
+			};
+			let indent: String = indent_at(content, anchor_start);
+			for (source, file) in incoming {
+				if !new_content.is_empty() {
+					new_content.push_str(&format!("\n{}", indent));
+				}
+				new_content.push_str(&file.whole_element.content);
+				let copy: bool = file.used || file.wanted_by.len() > 1;
+				let message: String = match (write, copy) {
+					(true, true) => format!("Copying file '{}' from '{}' to '{}' where it is referenced.", file.name, source.name, target.name),
+					(true, false) => format!("Moving file '{}' from '{}' to '{}' where it is referenced.", file.name, source.name, target.name),
+					(false, true) => format!("Could copy file '{}' from '{}' to '{}' where it is referenced.", file.name, source.name, target.name),
+					(false, false) => format!("Could move file '{}' from '{}' to '{}' where it is referenced.", file.name, source.name, target.name)
+				};
+				notes.push(Note::info(message).about(file.name.clone()).of_kind("moved_file"));
+				counters.add("moved_files", 1);
+			}
+			changes.push(Change::new(anchor.clone(), new_content));
+		}
+		// The originals nobody uses where they are.
+		for area in areas {
+			for file in area.files.iter().filter(|f| leaving(f) && !replaced.contains(&f.start)) {
+				changes.push(Change::new(file.whole_element.clone(), "".to_string()));
+			}
+		}
+		changes
+	}
+}
+
+/// How the name of a file present resembles that of a missing one, the
+/// closest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Resemblance {
+	Case,
+	Encoding,
+	Extension,
+	/// With the edit distance.
+	Spelling(usize)
+}
+
+impl Resemblance {
+	fn describe(&self) -> &'static str {
+		match self {
+			Resemblance::Case => "differs in case",
+			Resemblance::Encoding => "differs in encoding",
+			Resemblance::Extension => "differs in extension",
+			Resemblance::Spelling(_) => "similar name"
+		}
+	}
+
+	/// Likely the same file with the name written differently. A name
+	/// spelled alike may well be another file, e.g. "a.png" and "b.png".
+	fn same_file(&self) -> bool {
+		!matches!(self, Resemblance::Spelling(_))
+	}
+}
+
+fn resemblance(missing: &str, present: &str) -> Option<Resemblance> {
+	if missing.to_lowercase() == present.to_lowercase() {
+		return Some(Resemblance::Case);
+	}
+	let normal = |name: &str| pluginfile::file_name(&name.replace('+', " ")).to_lowercase();
+	let (missing, present) = (normal(missing), normal(present));
+	if missing == present {
+		return Some(Resemblance::Encoding);
+	}
+	let stem = |name: &str| -> String {
+		match name.rsplit_once('.') {
+			Some((stem, extension)) if !extension.contains('/') && !stem.ends_with('/') => stem.to_string(),
+			_ => name.to_string()
+		}
+	};
+	if stem(&missing) == stem(&present) {
+		return Some(Resemblance::Extension);
+	}
+	let distance: usize = edit_distance(&missing, &present);
+	if distance <= 2 && distance * 4 < missing.chars().count() {
+		return Some(Resemblance::Spelling(distance));
+	}
+	None
+}
+
+/// Levenshtein distance in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+	let b: Vec<char> = b.chars().collect();
+	let mut previous: Vec<usize> = (0..=b.len()).collect();
+	for (i, ca) in a.chars().enumerate() {
+		let mut current: Vec<usize> = vec![i + 1];
+		for (j, cb) in b.iter().enumerate() {
+			let substitution: usize = previous[j] + usize::from(ca != *cb);
+			current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+		}
+		previous = current;
+	}
+	previous[b.len()]
+}
+
+/// Replaces the references to `from` in `text` with ones to `to`, leaving
+/// references to names merely starting with `from` alone.
+fn replace_reference(text: &str, from: &str, to: &str) -> String {
+	let from: String = format!("@@PLUGINFILE@@{from}");
+	let mut result: String = String::new();
+	let mut rest: &str = text;
+	while let Some(position) = rest.find(&from) {
+		let end: usize = position + from.len();
+		let continues: bool = rest[end..].chars().next().is_some_and(|c| c.is_alphanumeric() || "._-%/".contains(c));
+		result.push_str(&rest[..position]);
+		if continues {
+			result.push_str(&from);
+		} else {
+			result.push_str(&format!("@@PLUGINFILE@@{to}"));
 		}
+		rest = &rest[end..];
 	}
+	result.push_str(rest);
+	result
+}
+
+/// The field owning an element, i.e. the element itself unless it is the
+/// `<text>` of a field.
+fn area_of<'a, 'input>(element: roxmltree::Node<'a, 'input>) -> roxmltree::Node<'a, 'input> {
+	match element.parent_element() {
+		Some(parent) if element.has_tag_name("text") && !parent.has_tag_name("question") => parent,
+		_ => element
+	}
+}
+
+/// The whitespace the line of `position` starts with.
+fn indent_at(content: &str, position: usize) -> String {
+	let line_start: usize = content[..position].rfind('\n').map(|i| i + 1).unwrap_or(0);
+	content[line_start..position].chars().take_while(|c| c.is_whitespace()).collect()
 }
 
 impl Action for FileAttachmentChecker {
-	fn process(&mut self, question: &Question, parser: &mut QParser, flags: Vec<String>) -> (bool, Vec<String>) {
-		let write = flags.contains(&"write".to_string());
-		let mut notes: Vec<String> = Vec::new();
-		let mut things_to_do: bool = false;
+	fn process(&mut self, question: &Question, parser: &mut QParser, context: &Context) -> Result<(Vec<Change>, Vec<Note>), ActionError> {
+		let write = context.write;
+		let mut notes: Vec<Note> = Vec::new();
+		let mut changes: Vec<Change> = Vec::new();
+		// Only counted in if we get through the question.
+		let mut counters: Counters = Counters::new();
+		self.linked.clear();
 
 		// iff A\B == ø -> delete(B\A) otherwise something is wrong.
 		//
 		// Basically, identify @@PLUGINFILES@@ referenced by the material and
 		// the files present. If all of the former are present in the latter
 		// remove from the latter those that are not in the former. Otherwise,
-		// assume that the identification of the former is broken.
-		
-		let re_dq = Regex::new("\"@@PLUGINFILE@@([^\"]*)\"").unwrap();
-		let re_q = Regex::new("'@@PLUGINFILE@@([^\"]*)'").unwrap();
-
-		// From raw to url-decoded.
-		let mut a: HashMap<String, String> = HashMap::new();
-		
-		for (_, [f]) in re_dq.captures_iter(question.whole_element.content.as_str()).map(|caps| caps.extract()) {
+		// assume that the identification of the former is broken. This is done
+		// per field, as a reference does not see the files of other fields.
+
+		let file_elements = parser.get_elements(question.index, vec!["file".to_string()]);
+		let text_elements = parser.get_elements(question.index, vec!["text".to_string()]);
+		let content: String = parser.get_current_content();
+		let options = roxmltree::ParsingOptions {
+			allow_dtd: true,
+			..roxmltree::ParsingOptions::default()
+		};
+		let doc = roxmltree::Document::parse_with_options(&content, options)
+			.map_err(|e| ActionError::new(format!("Could not parse the document: {}", e)))?;
+		let question_node = question_node(&doc, question.index)
+			.ok_or_else(|| ActionError::new("Could not find the question in the document.".to_string()))?;
+
+		let mut areas: Vec<Area> = Vec::new();
+		let mut area_index: HashMap<roxmltree::NodeId, usize> = HashMap::new();
+		let mut area_for = |node: roxmltree::Node, areas: &mut Vec<Area>| -> usize {
+			*area_index.entry(node.id()).or_insert_with(|| {
+				areas.push(Area {
+					name: element_path(node),
+					filearea: node.attribute("format").is_some() || node.children().any(|c| c.has_tag_name("file")),
+					references: Vec::new(),
+					files: Vec::new(),
+					text: None,
+					text_start: 0
+				});
+				areas.len() - 1
+			})
+		};
+
+		// The references, by the field of the text they are in.
+		for text in question_node.descendants().filter(|n| n.is_text()) {
+			let value: &str = text.text().unwrap_or_default();
+			if !value.contains("@@PLUGINFILE@@") {
+				continue;
+			}
+			let area: usize = match text.parent_element() {
+				Some(parent) => area_for(area_of(parent), &mut areas),
+				None => continue
+			};
 			// Note that some people have been adding get parameters to attachement file URLs.
 			// We assume that these have been used to deal with caches and ignore them.
-			let getless = f.split('?').next().unwrap();
-    		a.insert(f.to_string().clone(), url_decode(getless).unwrap().to_string());
-		}
-		for (_, [f]) in re_q.captures_iter(question.whole_element.content.as_str()).map(|caps| caps.extract()) {
-			let getless = f.split('?').next().unwrap();
-    		a.insert(f.to_string().clone(), url_decode(getless).unwrap().to_string());
+			for reference in pluginfile::references(value) {
+				if !areas[area].references.iter().any(|(raw, _)| *raw == reference.raw) {
+					areas[area].references.push((reference.raw, reference.name));
+				}
+			}
 		}
-		let mut matched: HashSet<String> = HashSet::new();
 
-		// Find the files.
-		let file_elements = parser.get_elements(question.index, vec!["file".to_string()]);
-		let mut to_delete: Vec<(String, ContentRef)> = Vec::new();
-		if file_elements.is_empty() && !a.is_empty() {
-			notes.push(" WARNING! Question has references to files but not files present.".to_string());
-			self.all_ok = false;
-		} else {
-			for file_element in &file_elements {
-                if let ContentType::Element(_name, whole_element_ref, _attributes_and_content) = file_element {
-                	let attachment_path: String = file_element.clone().get_attr("path".to_string()).expect("File element must have a 'path' attribute.").basic_entity_decode();
-                    let attachment_name: String = file_element.clone().get_attr("name".to_string()).expect("File element must have a 'name' attribute.").basic_entity_decode();
-                    let name = format!("{attachment_path}{attachment_name}");
-                    if a.contains_key(&name) {
-                    	// Direct match.
-                    	matched.insert(name.clone());
-                    } else {
-                    	let mut found = false;
-                    	// Maybe one of the urldecoded ones.
-                    	for (raw, decoded) in a.clone().into_iter() {
-                    		if decoded == name {
-                    			found = true;
-                    			matched.insert(raw.clone());
-                    		}
-                    	}
-                    	if !found {
-                    		// No reference seen. So push to be deleted.
-                    		to_delete.push((name,whole_element_ref.clone()));
-                    		// Do some bookkeepping.
-                    		self.removed_files += 1;
-                    		self.removed_bytes += whole_element_ref.content.len();
-                    	}
-                    }
-					// Do some bookkeepping.
-            		self.total_files += 1;
-            		self.total_bytes += whole_element_ref.content.len();
-                } else {
-                	panic!("Unexpected ContentType received as a search result.");
-                }
-            }
-		}
-
-		// Now did we match all?
-		if matched.len() == a.len() {
-			// So all matched can delete the ones.
-			if !to_delete.is_empty() {
-				things_to_do = true;
-				if write {
-					for (name, cref) in &to_delete {
-						notes.push(format!(" Deleting unused file '{}', saving {} bytes.", name, cref.content.len()));
-						let change: Change = Change::new(cref.clone(), "".to_string());
-						parser.register_change(change);
-					}
-				} else {
-					for (name, cref) in &to_delete {
-						notes.push(format!(" Could delete unused file '{}', and save {} bytes.", name, cref.content.len()));
-					}
+		// The files, by the field they are attached to. The parser lists the
+		// elements in document order.
+		let file_nodes: Vec<roxmltree::Node> = question_node.descendants().filter(|n| n.has_tag_name("file")).collect();
+		if file_nodes.len() != file_elements.len() {
+			return Err(ActionError::new("The files of the question do not add up.".to_string()));
+		}
+		for (file_element, file_node) in file_elements.iter().zip(file_nodes) {
+			if let ContentType::Element(_name, whole_element_ref, _attributes_and_content) = file_element {
+				let attachment_path: String = file_element.clone().get_attr("path".to_string())
+					.ok_or_else(|| ActionError::new("File element without a 'path' attribute.".to_string()))?.basic_entity_decode();
+				let attachment_name: String = file_element.clone().get_attr("name".to_string())
+					.ok_or_else(|| ActionError::new("File element without a 'name' attribute.".to_string()))?.basic_entity_decode();
+				let owner = file_node.parent_element().unwrap_or(question_node);
+				let area: usize = area_for(owner, &mut areas);
+				areas[area].files.push(AreaFile {
+					name: format!("{attachment_path}{attachment_name}"),
+					whole_element: whole_element_ref.clone(),
+					start: file_node.range().start,
+					used: false,
+					wanted_by: Vec::new()
+				});
+				// Do some bookkeepping.
+				counters.add("total_files", 1);
+				counters.add("total_bytes", whole_element_ref.content.len());
+			} else {
+				return Err(ActionError::new("Unexpected ContentType received as a search result.".to_string()));
+			}
+		}
+		let text_nodes = question_node.descendants().filter(|n| n.has_tag_name("text"));
+		for (text_element, text_node) in text_elements.iter().zip(text_nodes) {
+			if let ContentType::Element(_name, whole_element_ref, _parts) = text_element {
+				if let Some(area) = area_index.get(&area_of(text_node).id()) {
+					areas[*area].text = Some(whole_element_ref.clone());
+					areas[*area].text_start = text_node.range().start;
 				}
 			}
-		} else {
-			for (raw, _decoded) in a.clone().into_iter() {
-				if !matched.contains(&raw) {
-					notes.push(format!(" WARNING! References '{raw}', which was not matched."));
+		}
+
+		// Match the references, first in their own field then elsewhere.
+		// The referencing field, the reference raw and decoded.
+		let mut unmatched: Vec<(usize, String, String)> = Vec::new();
+		// The referencing field, the reference and the field and index of the file.
+		let mut misplaced: Vec<(usize, String, usize, usize)> = Vec::new();
+		for area in 0..areas.len() {
+			for (raw, decoded) in areas[area].references.clone() {
+				let matches = |f: &AreaFile| f.name == raw || f.name == decoded;
+				if let Some(file) = areas[area].files.iter_mut().find(|f| matches(f)) {
+					file.used = true;
+					continue;
+				}
+				let elsewhere: Option<(usize, usize)> = areas.iter().enumerate()
+					.find_map(|(other, a)| a.files.iter().position(&matches).map(|i| (other, i)));
+				match elsewhere {
+					// Maxima strings end up in some text, which one is anyone's guess.
+					Some((other, i)) if !areas[area].filearea => areas[other].files[i].used = true,
+					Some((other, i)) => {
+						if !areas[other].files[i].wanted_by.contains(&area) {
+							areas[other].files[i].wanted_by.push(area);
+						}
+						misplaced.push((area, raw, other, i));
+					},
+					None => unmatched.push((area, raw, decoded))
 				}
 			}
+		}
+
+		// The new contents of the `<text>` of fields, by field.
+		let mut rewrites: BTreeMap<usize, String> = BTreeMap::new();
+		unmatched.retain(|(area, raw, decoded)| {
+			let rewritten: bool = self.report_missing(&mut areas, *area, (raw, decoded), &mut rewrites, write, &mut notes);
+			counters.add("missing_references", 1);
+			if rewritten {
+				counters.add("rewritten_references", 1);
+			}
+			!rewritten
+		});
 
+		for file in areas.iter().flat_map(|a| a.files.iter()).filter(|f| !f.used && f.wanted_by.is_empty()) {
+			// Do some bookkeepping.
+			counters.add("removable_files", 1);
+			counters.add("removable_bytes", file.whole_element.content.len());
+		}
+
+		let references: usize = areas.iter().map(|a| a.references.len()).sum();
+		let files: usize = areas.iter().map(|a| a.files.len()).sum();
+		if files == 0 && references > 0 {
+			notes.push(Note::warning("Question has references to files but not files present.".to_string()).of_kind("no_attachments"));
+			counters.add("unmatched_questions", 1);
+		} else if unmatched.is_empty() {
+			// So all matched can delete the ones not referenced from anywhere.
+			for area in &areas {
+				for file in area.files.iter().filter(|f| !f.used && f.wanted_by.is_empty()) {
+					let cref: &ContentRef = &file.whole_element;
+					if write {
+						notes.push(Note::info(format!("Deleting unused file '{}', saving {} bytes.", file.name, cref.content.len())).about(file.name.clone()).of_kind("unused_file"));
+					} else {
+						notes.push(Note::info(format!("Could delete unused file '{}', and save {} bytes.", file.name, cref.content.len())).about(file.name.clone()).of_kind("unused_file"));
+					}
+					changes.push(Change::new(cref.clone(), "".to_string()));
+				}
+			}
+		} else {
 			// Did not find matches for all.
-			self.all_ok = false;
+			counters.add("unmatched_questions", 1);
+		}
+
+		for (area, raw, other, _) in &misplaced {
+			counters.add("cross_area_references", 1);
+			if !(self.move_files && write) {
+				notes.push(Note::error(format!("References '{raw}' from '{}', but the file is attached to '{}' where Moodle will not look for it.",
+					areas[*area].name, areas[*other].name)).about(raw.clone()).of_kind("cross_area_reference"));
+			}
+		}
+		if self.move_files {
+			let first: usize = changes.len();
+			changes.extend(self.move_misplaced(&areas, &content, &mut rewrites, write, &mut notes, &mut counters));
+			if changes.len() > first + 1 {
+				self.linked.push((first..changes.len()).collect());
+			}
+		}
+		for (area, new_content) in rewrites {
+			if let Some(text) = &areas[area].text {
+				changes.push(Change::new(text.clone(), new_content));
+			}
 		}
 
-		(things_to_do, notes)
+		self.counters.merge(&counters);
+		Ok((changes, notes))
 	}
 
 	fn name(&self) -> String {
@@ -147,8 +524,35 @@ detects and can remove such files present in the question.xml.
 The primary source for such files is duplication of questions, expect the tool
 to often list the very same files for many questions in a row.
 
+Moodle looks for the files of each field, e.g. the question text, general
+feedback or the feedback of a PRT node, only among the files attached to
+that field. References to files attached to some other field are reported
+as errors, with `--files-move` such files get moved to where they are used.
+
+References to files not present at all are listed along with similarly
+named files, with `--files-fix-missing` the reference is rewritten to point
+to the one differing only in case, encoding or extension.
+
 Note that this tool will not do de-duplication or access right tuning so the
-end result might still not be the smallest possible.".to_string()
+end result might still not be the smallest possible, --dupfiles reports files
+shared by many questions.".to_string()
+	}
+
+	fn options(&self) -> Vec<OptionSpec> {
+		vec![
+			OptionSpec::new("move", OptionKind::Flag,
+				"Move files referenced from another field to the field referencing them,
+or copy them if also used where they are."),
+			OptionSpec::new("fix-missing", OptionKind::Flag,
+				"Point references to missing files to the file present that differs only
+in case, encoding or extension. Files with similar names are only listed.")
+		]
+	}
+
+	fn configure(&mut self, options: &OptionValues) -> Result<(), String> {
+		self.move_files = options.flag("move");
+		self.fix_missing = options.flag("fix-missing");
+		Ok(())
 	}
 
 	fn supports(&self, _qtype: String) -> bool {
@@ -156,13 +560,28 @@ end result might still not be the smallest possible.".to_string()
 		true
 	}
 
+	fn runs_after(&self) -> Vec<String> {
+		// Rewritten references replace the whole text, so rewrite the
+		// converted one when both are in use.
+		vec!["stacklang".to_string()]
+	}
+
+	fn linked_changes(&self) -> Vec<Vec<usize>> {
+		self.linked.clone()
+	}
+
 	fn report(&self) -> Option<String> {
 		// Maybe tell how many files and how much space.
-		if self.total_files > 0 {
+		if self.counters.get("total_files") > 0 {
 			let mut result: String = format!("Saw {} files of which {} could be removed.
 In total those files take {} bytes of room and the removable ones {}.",
-				 self.total_files, self.removed_files, self.total_bytes, self.removed_bytes);
-			if !self.all_ok {
+				 self.counters.get("total_files"), self.counters.get("removable_files"),
+				 self.counters.get("total_bytes"), self.counters.get("removable_bytes"));
+			if self.counters.get("missing_references") > 0 {
+				result.push_str(&format!("\n{} references were to missing files, {} of them rewritten to similar files.",
+					self.counters.get("missing_references"), self.counters.get("rewritten_references")));
+			}
+			if self.counters.get("unmatched_questions") > 0 {
 				result.push_str("
 
 NOTE! That some questions had references to files that could not be matched by current logic.
@@ -173,4 +592,12 @@ Any extra files those questions might have had were not removed.");
 			None	
 		}
 	}
+
+	fn counters(&self) -> Counters {
+		self.counters.clone()
+	}
+
+	fn merge(&mut self, other: &dyn Action) {
+		self.counters.merge(&other.counters());
+	}
 }
\ No newline at end of file
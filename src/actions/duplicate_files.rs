@@ -0,0 +1,195 @@
+//! Finding attachments with identical content across questions, typically
+//! left behind by duplicating questions, so that they can be considered
+//! for moving to a shared resource.
+//!
+//! Files are compared by the SHA-256 of their decoded content, so the same
+//! image under different names is found too.
+
+use position_preserving_moodle_question_xml_edit::{Change, QParser, Question};
+use crate::action::{Action, ActionError, Context, Counters, Note};
+use crate::actions::attachment_files::{decoded, question_files, QuestionFile};
+use crate::inputs::STDIN;
+use crate::journal::sha256_hex;
+use crate::options::{OptionKind, OptionSpec, OptionValues};
+use std::any::Any;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Where a file was seen.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Occurrence {
+	file: String,
+	/// 1-based index of the question.
+	question: usize,
+	question_name: String,
+	/// The field it is attached to, e.g. "questiontext" or "answer[2]".
+	field: String,
+	/// Path and name of the attachment, e.g. "/image.png".
+	name: String
+}
+
+/// The files with the same content.
+#[derive(Debug, Clone, Default)]
+struct Group {
+	/// Decoded size in bytes.
+	size: usize,
+	/// Size as base64 in the XML.
+	encoded_size: usize,
+	occurrences: BTreeSet<Occurrence>
+}
+
+impl Group {
+	/// Bytes taken by all but one of the copies.
+	fn wasted(&self) -> usize {
+		self.size * self.occurrences.len().saturating_sub(1)
+	}
+
+	/// The number of distinct questions having a copy.
+	fn questions(&self) -> usize {
+		self.occurrences.iter().map(|o| (&o.file, o.question)).collect::<BTreeSet<_>>().len()
+	}
+}
+
+pub struct DuplicateFileReporter {
+	// Groups of files by the hex SHA-256 of their content.
+	groups: BTreeMap<String, Group>,
+	// How many of the groups with copies to list in the report.
+	limit: usize
+}
+
+impl Default for DuplicateFileReporter {
+	fn default() -> Self {
+		DuplicateFileReporter::new()
+	}
+}
+
+impl DuplicateFileReporter {
+	/// Simple initialisation logic.
+	pub fn new() -> DuplicateFileReporter {
+		DuplicateFileReporter {
+			groups: BTreeMap::new(),
+			limit: 20
+		}
+	}
+
+	/// The groups with more than one copy, the most wasteful first.
+	fn duplicated(&self) -> Vec<&Group> {
+		let mut result: Vec<&Group> = self.groups.values().filter(|g| g.occurrences.len() > 1).collect();
+		result.sort_by(|a, b| b.wasted().cmp(&a.wasted()).then(a.occurrences.cmp(&b.occurrences)));
+		result
+	}
+}
+
+impl Action for DuplicateFileReporter {
+	fn process(&mut self, question: &Question, parser: &mut QParser, context: &Context) -> Result<(Vec<Change>, Vec<Note>), ActionError> {
+		let files: Vec<QuestionFile> = question_files(question, parser)?;
+		let question_name: String = question.name.unwrap_cdata();
+
+		for file in &files {
+			// Files that do not decode are someone else's problem.
+			let data: Vec<u8> = match decoded(file) {
+				Ok(data) => data,
+				Err(_) => continue
+			};
+			let hash: String = sha256_hex(&data);
+			let group: &mut Group = self.groups.entry(hash).or_default();
+			group.size = data.len();
+			group.encoded_size = file.content.as_ref().map(|c| c.content.len()).unwrap_or(0);
+			group.occurrences.insert(Occurrence {
+				file: context.file.clone().unwrap_or(STDIN.to_string()),
+				question: question.index + 1,
+				question_name: question_name.clone(),
+				field: file.field.clone(),
+				name: file.name.clone()
+			});
+		}
+		Ok((Vec::new(), Vec::new()))
+	}
+
+	fn name(&self) -> String {
+		"Attachment duplicate report".to_string()
+	}
+
+	fn flag(&self) -> String {
+		"dupfiles".to_string()
+	}
+
+	fn description(&self) -> String {
+		"Finds attachments with identical content across all the processed questions,
+whatever their names. The report lists which questions share each file and
+how many bytes the copies take, for deciding whether to move them to a shared
+resource. Changes nothing.".to_string()
+	}
+
+	fn options(&self) -> Vec<OptionSpec> {
+		vec![
+			OptionSpec::new("limit", OptionKind::Number,
+				"How many of the shared files to list, those taking the most room first,
+0 lists all.").default("20")
+		]
+	}
+
+	fn configure(&mut self, options: &OptionValues) -> Result<(), String> {
+		self.limit = options.number("limit").unwrap_or(20);
+		Ok(())
+	}
+
+	fn supports(&self, _qtype: String) -> bool {
+		true
+	}
+
+	fn report(&self) -> Option<String> {
+		if self.groups.is_empty() {
+			return None;
+		}
+		let counters: Counters = self.counters();
+		let duplicated: Vec<&Group> = self.duplicated();
+		let mut result: String = format!("Saw {} files with {} distinct contents, {} of them in more than one place.",
+			counters.get("total_files"), self.groups.len(), duplicated.len());
+		if duplicated.is_empty() {
+			return Some(result);
+		}
+		result.push_str(&format!("\nThe extra copies take {} bytes, {} bytes as base64 in the XML.",
+			counters.get("wasted_bytes"), counters.get("wasted_encoded_bytes")));
+		let listed: usize = if self.limit == 0 { duplicated.len() } else { self.limit.min(duplicated.len()) };
+		for group in &duplicated[..listed] {
+			result.push_str(&format!("\n\n{} copies in {} questions, {} bytes each:", group.occurrences.len(), group.questions(), group.size));
+			for o in &group.occurrences {
+				result.push_str(&format!("\n {} question {} '{}' {}: '{}'", o.file, o.question, o.question_name, o.field, o.name));
+			}
+		}
+		if listed < duplicated.len() {
+			result.push_str(&format!("\n\n{} more shared files not listed, see --dupfiles-limit.", duplicated.len() - listed));
+		}
+		Some(result)
+	}
+
+	fn counters(&self) -> Counters {
+		let mut counters: Counters = Counters::new();
+		for group in self.groups.values() {
+			counters.add("total_files", group.occurrences.len());
+			counters.add("total_bytes", group.size * group.occurrences.len());
+			if group.occurrences.len() > 1 {
+				counters.add("shared_contents", 1);
+				counters.add("duplicate_files", group.occurrences.len() - 1);
+				counters.add("wasted_bytes", group.wasted());
+				counters.add("wasted_encoded_bytes", group.encoded_size * (group.occurrences.len() - 1));
+			}
+		}
+		counters
+	}
+
+	fn merge(&mut self, other: &dyn Action) {
+		if let Some(other) = other.as_any().and_then(|a| a.downcast_ref::<DuplicateFileReporter>()) {
+			for (hash, theirs) in &other.groups {
+				let group: &mut Group = self.groups.entry(hash.clone()).or_default();
+				group.size = theirs.size;
+				group.encoded_size = theirs.encoded_size;
+				group.occurrences.extend(theirs.occurrences.iter().cloned());
+			}
+		}
+	}
+
+	fn as_any(&self) -> Option<&dyn Any> {
+		Some(self)
+	}
+}
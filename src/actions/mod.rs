@@ -1,3 +1,20 @@
 pub mod attachments;
+pub mod attachment_files;
+pub mod duplicate_files;
 pub mod stack_lang;
-pub mod stack_extractor;
\ No newline at end of file
+pub mod stack_extractor;
+
+use crate::action::Action;
+
+/// All the actions shipped with the tool. New actions are added here,
+/// their running order comes from their priorities and dependencies.
+pub fn builtin_actions() -> Vec<Box<dyn Action>> {
+	vec![
+		Box::new(attachments::FileAttachmentChecker::new()),
+		Box::new(attachment_files::FileExporter::new()),
+		Box::new(attachment_files::FileImporter::new()),
+		Box::new(duplicate_files::DuplicateFileReporter::new()),
+		Box::new(stack_lang::LangSyntaxConverter::new()),
+		Box::new(stack_extractor::StackExtractor::new())
+	]
+}
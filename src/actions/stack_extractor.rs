@@ -1,43 +1,41 @@
 //! Extracts specific fields from STACK questions and prints them out
 //! Intended for grepping with some more accuracy.
 
-use position_preserving_moodle_question_xml_edit::{QParser, Question};
+use position_preserving_moodle_question_xml_edit::{Change, QParser, Question};
 use position_preserving_moodle_question_xml_edit::stack::{STACKQuestion};
-use crate::action::Action;
+use crate::action::{Action, ActionError, Context, Note};
+use crate::options::{OptionKind, OptionSpec, OptionValues};
 
 
 pub struct StackExtractor {
+	// The output-format, i.e. the prefix of lines.
+	of: usize,
+	// The parts to print.
+	parts: Vec<String>
+}
+
+impl Default for StackExtractor {
+	fn default() -> Self {
+		StackExtractor::new()
+	}
 }
 
 impl StackExtractor {
 	/// Simple initialisation logic.
 	pub fn new() -> StackExtractor {
-		StackExtractor {}
+		StackExtractor {
+			of: 2,
+			parts: vec!["qt".to_string()]
+		}
 	}
 }
 
 
 impl Action for StackExtractor {
-	fn process(&mut self, question: &Question, parser: &mut QParser, flags: Vec<String>) -> (bool, Vec<String>) {
-		let mut notes: Vec<String> = Vec::new();
-		// First identify all the flags.
-		let mut of: usize = 2;
-		if flags.contains(&"of=0".to_string()) {
-			of = 0;
-		}
-		if flags.contains(&"of=1".to_string()) {
-			of = 1;
-		}
-		let mut parts:Vec<String> = Vec::new();
-		let partflags: Vec<String> = flags.into_iter().filter(|a| a.starts_with("parts=")).map(|a| a[6..].to_string()).collect();
-		for flag in partflags {
-			for bit in flag.split(",") {
-				parts.push(bit.trim().to_string());
-			}
-		}
-		if parts.is_empty() {
-			parts.push("qt".to_string());
-		}
+	fn process(&mut self, question: &Question, parser: &mut QParser, _context: &Context) -> Result<(Vec<Change>, Vec<Note>), ActionError> {
+		let mut notes: Vec<Note> = Vec::new();
+		let of: usize = self.of;
+		let parts: &Vec<String> = &self.parts;
 
 		let stack_question: STACKQuestion = parser.get_as_stack_question(question.index);
 
@@ -52,55 +50,55 @@ impl Action for StackExtractor {
 			let qv = stack_question.questionvariables.unwrap_cdata();
 			if !qv.is_empty() {
 				for line in qv.split("\n") {
-					notes.push(format!("{prefix}{line}"));
+					notes.push(Note::raw(format!("{prefix}{line}")).about("questionvariables".to_string()));
 				}
 			}
 		}
 		if parts.contains(&"qt".to_string()) || parts.contains(&"ct".to_string()) {
-			let qt = stack_question.questiontext.get_content().unwrap().unwrap_cdata();
+			let qt = stack_question.questiontext.clone().get_content().ok_or_else(|| ActionError::new("No content in questiontext.".to_string()))?.unwrap_cdata();
 			if !qt.is_empty() {
 				for line in qt.split("\n") {
-					notes.push(format!("{prefix}{line}"));
+					notes.push(Note::raw(format!("{prefix}{line}")).about("questiontext".to_string()));
 				}
 			}
 		}
 		if parts.contains(&"gf".to_string()) || parts.contains(&"ct".to_string()) {
-			let gf = stack_question.generalfeedback.get_content().unwrap().unwrap_cdata();
+			let gf = stack_question.generalfeedback.clone().get_content().ok_or_else(|| ActionError::new("No content in generalfeedback.".to_string()))?.unwrap_cdata();
 			if !gf.is_empty() {
 				for line in gf.split("\n") {
-					notes.push(format!("{prefix}{line}"));
+					notes.push(Note::raw(format!("{prefix}{line}")).about("generalfeedback".to_string()));
 				}
 			}
 		}
 
-		for (_prtname, prt) in stack_question.prts.clone().into_iter() {
+		for (prtname, prt) in stack_question.prts.clone().into_iter() {
 			if parts.contains(&"kv".to_string()) {
 				let fv = prt.feedbackvariables.unwrap_cdata();
 				if !fv.is_empty() {
 					for line in fv.split("\n") {
-						notes.push(format!("{prefix}{line}"));
+						notes.push(Note::raw(format!("{prefix}{line}")).about(format!("{prtname}/feedbackvariables")));
 					}	
 				}
 			}
 			if parts.contains(&"ct".to_string()) {
 				for i in 0..prt.nodes.len() {
-					let tf = prt.nodes[i].truefeedback.clone().get_content().unwrap().unwrap_cdata();
+					let tf = prt.nodes[i].truefeedback.clone().get_content().ok_or_else(|| ActionError::new("No content in truefeedback.".to_string()))?.unwrap_cdata();
 					if !tf.is_empty() {
 						for line in tf.split("\n") {
-							notes.push(format!("{prefix}{line}"));
+							notes.push(Note::raw(format!("{prefix}{line}")).about(format!("{prtname}/{i}/truefeedback")));
 						}
 					}
-					let ff = prt.nodes[i].falsefeedback.clone().get_content().unwrap().unwrap_cdata();
+					let ff = prt.nodes[i].falsefeedback.clone().get_content().ok_or_else(|| ActionError::new("No content in falsefeedback.".to_string()))?.unwrap_cdata();
 					if !ff.is_empty() {
 						for line in ff.split("\n") {
-							notes.push(format!("{prefix}{line}"));
+							notes.push(Note::raw(format!("{prefix}{line}")).about(format!("{prtname}/{i}/falsefeedback")));
 						}
 					}
 				}
 			}
 		}
 
-		(false, notes)
+		Ok((Vec::new(), notes))
 	}
 
 
@@ -114,20 +112,35 @@ impl Action for StackExtractor {
 
 	fn description(&self) -> String {
 		"Prints out parts of STACK questions, for grepping. To use, define the parts
-and the output-format.
-
-The output-format controls the prefix added to each line.
- --of=0 no prefix
- --of=1 question index
- --of=2 question name [default]
-
-The outputted partnames can be given as a comma separated list, e.g --parts=qt,gf
- --parts=qt question text [default]
- --parts=gf general fedback
- --parts=qv question variables
- --parts=ct key castext, question text, general feedback and PRT feedbacks
- --parts=kv keyvals, question variables and PRT feedback variables
-".to_string()
+and the output-format, e.g. --stackextract-parts=qt,gf --stackextract-of=0".to_string()
+	}
+
+	fn options(&self) -> Vec<OptionSpec> {
+		let parts: Vec<String> = ["qt", "gf", "qv", "ct", "kv"].iter().map(|p| p.to_string()).collect();
+		let formats: Vec<String> = ["0", "1", "2"].iter().map(|p| p.to_string()).collect();
+		vec![
+			OptionSpec::new("of", OptionKind::Choice(formats),
+				"The prefix added to each line:
+ 0 no prefix
+ 1 question index
+ 2 question name").default("2"),
+			OptionSpec::new("parts", OptionKind::List(parts),
+				"The parts to output, as a comma separated list:
+ qt question text
+ gf general fedback
+ qv question variables
+ ct key castext, question text, general feedback and PRT feedbacks
+ kv keyvals, question variables and PRT feedback variables").default("qt")
+		]
+	}
+
+	fn configure(&mut self, options: &OptionValues) -> Result<(), String> {
+		self.of = options.number("of").unwrap_or(2);
+		self.parts = options.list("parts");
+		if self.parts.is_empty() {
+			self.parts.push("qt".to_string());
+		}
+		Ok(())
 	}
 
 	fn supports(&self, qtype: String) -> bool {
@@ -135,6 +148,11 @@ The outputted partnames can be given as a comma separated list, e.g --parts=qt,g
 		qtype == *"stack"
 	}
 
+	fn runs_after(&self) -> Vec<String> {
+		// Extract the converted version when both are in use.
+		vec!["stacklang".to_string()]
+	}
+
 	fn report(&self) -> Option<String> {
 		None
 	}
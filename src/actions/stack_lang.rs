@@ -7,43 +7,50 @@
 use position_preserving_moodle_question_xml_edit::{QParser, Question, Change};
 use position_preserving_moodle_question_xml_edit::stack::{STACKQuestion, STACKPath};
 use stack_maxima_parser::parser::{StackMaximaParser, MPNode, MPNodeType, StackStringUsage};
-use crate::action::Action;
+use crate::action::{Action, ActionError, Context, Counters, Note, stack_path_string};
 use regex::Regex;
 
 pub struct LangSyntaxConverter {
-	// Random stats
-	multilang_conversions: usize,
-	mlang_conversions: usize,
-	// Did we see MCQ inputs with difficult strings?
-	saw_mcq: bool,
-	// Did we see fragments in logic? i.e. start of lang-block but no end in the same "string".
-	saw_logic_fragments: bool,
-	// Somethign truly odd?
-	saw_oddities: bool
+	// Random stats, "multilang_conversions" and "mlang_conversions".
+	// Did we see MCQ inputs with difficult strings? "mcq_oddities".
+	// Did we see fragments in logic? i.e. start of lang-block but no end in the same "string". "logic_fragments".
+	// Somethign truly odd? "oddities".
+	counters: Counters,
+	// The relocated `tans`-values of the last question and the question
+	// variables defining them, which only work together.
+	linked: Vec<Vec<usize>>
+}
+
+impl Default for LangSyntaxConverter {
+	fn default() -> Self {
+		LangSyntaxConverter::new()
+	}
 }
 
 impl LangSyntaxConverter {
 	/// Simple initialisation logic.
 	pub fn new() -> LangSyntaxConverter {
 		LangSyntaxConverter {
-			multilang_conversions: 0,
-			mlang_conversions: 0,
-			saw_mcq: false,
-			saw_logic_fragments: false,
-			saw_oddities: false
+			counters: Counters::new(),
+			linked: Vec::new()
 		}
 	}
 }
 
 
 impl Action for LangSyntaxConverter {
-	fn process(&mut self, question: &Question, parser: &mut QParser, flags: Vec<String>) -> (bool, Vec<String>) {
-		let write = flags.contains(&"write".to_string());
-		let mut notes: Vec<String> = Vec::new();
-		let mut things_to_do: bool = false;
+	fn process(&mut self, question: &Question, parser: &mut QParser, context: &Context) -> Result<(Vec<Change>, Vec<Note>), ActionError> {
+		let write = context.write;
+		let mut notes: Vec<Note> = Vec::new();
+		// Changes and counts are only handed out once we get through the whole question.
+		let mut pending: Vec<Change> = Vec::new();
+		let mut counters: Counters = Counters::new();
+		self.linked.clear();
+		// Indices of the changes relocating `tans`-values.
+		let mut relocated: Vec<usize> = Vec::new();
 
 		// Get a better access to the contents.
-		let mut stack_question: STACKQuestion = parser.get_as_stack_question(question.index);
+		let stack_question: STACKQuestion = parser.get_as_stack_question(question.index);
 
 		// Separate permutations to keep the pattern simple.
 		let re_multilang_p1 = Regex::new("(?s)<span\\s+lang=\"([a-zA-Z0-9_\\-]+)\"\\s+class=\"multilang\"\\s*>(.*?)<\\/span>").unwrap();
@@ -59,7 +66,8 @@ impl Action for LangSyntaxConverter {
 			// Note by "currently", we mean that the current XML-serialisation
 			// logic does not skip empty fields. Should that change the library
 			// will probably simply not return those fields.
-			let mut full_content = ct.clone().get_content().expect("These will currently always have content.").unwrap_cdata();
+			let content_ref = ct.clone().get_content().ok_or_else(|| ActionError::new("CASText field without content.".to_string()))?;
+			let mut full_content = content_ref.unwrap_cdata();
 			let mut changes = false;
 			for (whole, [lang, content]) in re_multilang_p1.captures_iter(&full_content.clone()).map(|caps| caps.extract()) {
 				let start = full_content.find(whole).expect("Well it was found already.");
@@ -69,7 +77,7 @@ impl Action for LangSyntaxConverter {
 				new_content.push_str(&full_content[whole.len()+start..]);
 				full_content = new_content;
 				changes = true;
-				self.multilang_conversions += 1;
+				counters.add("multilang_conversions", 1);
 				qmod_count += 1;
 			}
 			for (whole, [lang, content]) in re_multilang_p2.captures_iter(&full_content.clone()).map(|caps| caps.extract()) {
@@ -80,7 +88,7 @@ impl Action for LangSyntaxConverter {
 				new_content.push_str(&full_content[whole.len()+start..]);
 				full_content = new_content;
 				changes = true;
-				self.multilang_conversions += 1;
+				counters.add("multilang_conversions", 1);
 				qmod_count += 1;
 			}
 
@@ -93,16 +101,13 @@ impl Action for LangSyntaxConverter {
 				new_content.push_str(&full_content[whole.len()+start..]);
 				full_content = new_content;
 				changes = true;
-				self.mlang_conversions += 1;
+				counters.add("mlang_conversions", 1);
 				qmod_count += 1;
 			}
 
 			if changes {
-				things_to_do = true;
-				if write {
-					let change: Change = Change::cdata_wrapped_version(ct.get_content().unwrap().clone(), full_content);
-					parser.register_change(change);
-				}
+				let change: Change = Change::cdata_wrapped_version(content_ref, full_content);
+				pending.push(change);
 			}
 		}
 
@@ -113,21 +118,21 @@ impl Action for LangSyntaxConverter {
 		for (inputname, input) in stack_question.inputs.clone().into_iter() {
 			match input.r#type.unwrap_cdata().as_str() {
 				"checkbox" | "dropdown" | "radio" => {
-					notes.push(format!("  - MCQ input '{}'.", inputname));
+					notes.push(Note::info(format!("MCQ input '{}'.", inputname)).at_level(2).about(format!("{inputname}/tans")).of_kind("mcq_input"));
 					// Check for locally, in TANS defined options.
 					let mut mparser = StackMaximaParser::new_no_insertions();
 					let mut rawtans = input.tans.unwrap_cdata();
 					let tans: Option<MPNode> = mparser.parse(rawtans.clone());
 
 					if tans.is_none() {
-						notes.push("   + Issues parsing the `tans`-value.".to_string());
-					} else if let MPNodeType::Root(statements,_,_) = tans.unwrap().value {
-						if let MPNodeType::Statement(expr, _) = &statements[0].value {
+						notes.push(Note::warning("Issues parsing the `tans`-value.".to_string()).at_level(3).about(format!("{inputname}/tans")).of_kind("unparseable_tans"));
+					} else if let Some(MPNode {value: MPNodeType::Root(statements,_,_), ..}) = tans {
+						if let Some(MPNode {value: MPNodeType::Statement(expr, _), ..}) = statements.first() {
 							// The question is whether the "expr" is an identifier
 							// or a list.
 							match &expr.value {
 								MPNodeType::Identifier(id) => {
-									notes.push(format!("   + Options defined in question-variables as '{id}'."));
+									notes.push(Note::info(format!("Options defined in question-variables as '{id}'.")).at_level(3).about(format!("{inputname}/tans")).of_kind("mcq_options_in_qv"));
 								},
 								MPNodeType::List(items) => {
 									// Now there is a possibility that the list has labels.
@@ -140,7 +145,7 @@ impl Action for LangSyntaxConverter {
 												// Extract possible strings.
 												let strings = inner_items[2].extract_stack_string_usage(StackStringUsage::ListElement(2));
 												if strings.len() > 1 {
-													notes.push("   + Complicated label, could not inspect fragments.".to_string());
+													notes.push(Note::warning("Complicated label, could not inspect fragments.".to_string()).at_level(3).about(format!("{inputname}/tans")).of_kind("complicated_label"));
 												} else if strings.len() == 1 {
 													// So a single string, this we can work with.
 													let stringvalue = if let MPNodeType::String(v) = &strings[0].1.value {v.clone()} else {String::new()};
@@ -153,7 +158,7 @@ impl Action for LangSyntaxConverter {
 														new_content.push_str(&format!("[[lang code='{lang}']]{content}[[/lang]]"));
 														new_content.push_str(&modified[whole.len()+start..]);
 														modified = new_content;
-														self.multilang_conversions += 1;
+														counters.add("multilang_conversions", 1);
 														qmod_count += 1;
 													}
 													for (whole, [lang, content]) in re_multilang_p2.captures_iter(&modified.clone()).map(|caps| caps.extract()) {
@@ -163,7 +168,7 @@ impl Action for LangSyntaxConverter {
 														new_content.push_str(&format!("[[lang code='{lang}']]{content}[[/lang]]"));
 														new_content.push_str(&modified[whole.len()+start..]);
 														modified = new_content;
-														self.multilang_conversions += 1;
+														counters.add("multilang_conversions", 1);
 														qmod_count += 1;
 													}
 
@@ -175,7 +180,7 @@ impl Action for LangSyntaxConverter {
 														new_content.push_str(&format!("[[lang code='{trimmedlang}']]{content}[[/lang]]"));
 														new_content.push_str(&modified[whole.len()+start..]);
 														modified = new_content;
-														self.mlang_conversions += 1;
+														counters.add("mlang_conversions", 1);
 														qmod_count += 1;
 													}
 
@@ -194,8 +199,8 @@ impl Action for LangSyntaxConverter {
 																	newstring.push_str(&rawtans[strings[0].1.position.endbyte..rawtans.len()]);
 																	rawtans = newstring;
 																} else {
-																	self.saw_mcq = true;
-																	notes.push("   + Localisation spotted in value not in label? Not touching this.".to_string());
+																	counters.add("mcq_oddities", 1);
+																	notes.push(Note::warning("Localisation spotted in value not in label? Not touching this.".to_string()).at_level(3).about(format!("{inputname}/tans")).of_kind("lang_in_value"));
 																}
 															},
 															StackStringUsage::CASText => {
@@ -209,45 +214,43 @@ impl Action for LangSyntaxConverter {
 																rawtans = newstring;
 															},
 															_ => {
-																panic!("Unexpected string use declared.");
+																return Err(ActionError::new(format!("Unexpected string use declared in the `tans` of '{inputname}'.")));
 															}
 														}
 													}
 												} else {
-													self.saw_mcq = true;
-													notes.push("   + Stringless custom-label, probably defined elsewhere.".to_string());
+													counters.add("mcq_oddities", 1);
+													notes.push(Note::warning("Stringless custom-label, probably defined elsewhere.".to_string()).at_level(3).about(format!("{inputname}/tans")).of_kind("stringless_label"));
 												}
 											} else {
 												// No custom label
 											}
 										} else {
-											self.saw_mcq = true;
-											notes.push("   + Odd definition of options, please provide sample to tool devs.".to_string());
+											counters.add("mcq_oddities", 1);
+											notes.push(Note::warning("Odd definition of options, please provide sample to tool devs.".to_string()).at_level(3).about(format!("{inputname}/tans")).of_kind("odd_options"));
 										}
 									}
 								},
 								_ => {
-									self.saw_mcq = true;
-									notes.push("   + Found unexpected expression-type in the `tans`-field.".to_string());
+									counters.add("mcq_oddities", 1);
+									notes.push(Note::warning("Found unexpected expression-type in the `tans`-field.".to_string()).at_level(3).about(format!("{inputname}/tans")).of_kind("unexpected_tans"));
 								}
 							}
 						}
 					} else {
-						panic!("Parser returned something odd");
+						return Err(ActionError::new(format!("Parser returned something odd for the `tans` of '{inputname}'.")));
 					}
 
 					if input.tans.unwrap_cdata() != rawtans {
-						notes.push("   + Modified locally defined `tans`-value.".to_string());
-						notes.push("   + Transferred definition to the end of question-variables. As inline CASText requires that.".to_string());
-						things_to_do = true;
-						if write {
-							let mut label: String = String::from("auto_relocated_");
-							label.push_str(&input.name.unwrap_cdata());
-							label.push_str("_options");
-							question_variables.push_str(&format!("\n\n{label}: {rawtans};"));
-							let change: Change = Change::cdata_wrapped_version(input.tans.clone(), label);
-							parser.register_change(change);
-						}
+						notes.push(Note::info("Modified locally defined `tans`-value.".to_string()).at_level(3).about(format!("{inputname}/tans")).of_kind("tans_relocated"));
+						notes.push(Note::info("Transferred definition to the end of question-variables. As inline CASText requires that.".to_string()).at_level(3).about(format!("{inputname}/tans")).of_kind("tans_relocated"));
+						let mut label: String = String::from("auto_relocated_");
+						label.push_str(&input.name.unwrap_cdata());
+						label.push_str("_options");
+						question_variables.push_str(&format!("\n\n{label}: {rawtans};"));
+						let change: Change = Change::cdata_wrapped_version(input.tans.clone(), label);
+						relocated.push(pending.len());
+						pending.push(change);
 					}
 
 					
@@ -258,20 +261,18 @@ impl Action for LangSyntaxConverter {
 			}
 		}
 
-		// If question-variables changed during MCQ-processing commit that.
-		if stack_question.questionvariables.unwrap_cdata() != question_variables {
-			let change: Change = Change::cdata_wrapped_version(stack_question.questionvariables.clone(), question_variables);
-			if write {
-				parser.register_change(change);
-				// If we modified the question variables the following steps will need new
-				// version of them.
-				stack_question = parser.get_as_stack_question(question.index);
-			}
-		}
+		// If question-variables changed during MCQ-processing, the logic below
+		// continues from that version and commits both at the same time.
 
 		// Check rest of the logic.
 		for (path, keyval) in stack_question.get_keyval_fields() {
-			let mut unwrapped: String = keyval.unwrap_cdata();
+			let target: String = stack_path_string(&path);
+			let is_question_variables: bool = matches!(&path, STACKPath::Root(field) if field == "questionvariables");
+			let mut unwrapped: String = if is_question_variables {
+				question_variables.clone()
+			} else {
+				keyval.unwrap_cdata()
+			};
 			if unwrapped.contains("mlang") || unwrapped.contains("multilang") {
 				// Depending on where we are we might have different level of trust
 				// on what we target.
@@ -282,21 +283,23 @@ impl Action for LangSyntaxConverter {
 				match path {
 					STACKPath::Root(_) => {
 						// Only question variables in root.
-						notes.push("  - Has specific sequences in question variables.".to_string());
+						notes.push(Note::info("Has specific sequences in question variables.".to_string()).at_level(2).about(target.clone()).of_kind("keyval_sequences"));
 						target_third_elements = true;
 					},
 					STACKPath::PRT(name,_) => {
 						// Only feedback-variables in PRTs.
-						notes.push(format!("  - Has specific sequences in {} feedback variables.", name));
+						notes.push(Note::info(format!("Has specific sequences in {} feedback variables.", name)).at_level(2).about(target.clone()).of_kind("keyval_sequences"));
 					}
 					_ => {
-						panic!("Unexpected new type of keyval block! Maybe this logic needs to be reworked.");
+						return Err(ActionError::new(format!("Unexpected new type of keyval block '{target}'! Maybe this logic needs to be reworked.")));
 					}					
 				}
 
 				let mut mparser = StackMaximaParser::new_with_insert_semicolons();
 				let parsedkeyval: Option<MPNode> = mparser.parse(unwrapped.clone());
-				let stringuses = parsedkeyval.expect("Something was syntactically broken.").extract_stack_string_usage(StackStringUsage::Unknown);
+				let stringuses = parsedkeyval
+					.ok_or_else(|| ActionError::new(format!("Could not parse '{target}', something was syntactically broken.")))?
+					.extract_stack_string_usage(StackStringUsage::Unknown);
 
 				for (typeofuse, stringnode) in stringuses.iter().rev() {
 					if let MPNodeType::String(value) = &stringnode.value {
@@ -312,7 +315,7 @@ impl Action for LangSyntaxConverter {
 							new_content.push_str(&format!("[[lang code='{lang}']]{content}[[/lang]]"));
 							new_content.push_str(&modified[whole.len()+start..]);
 							modified = new_content;
-							self.multilang_conversions += 1;
+							counters.add("multilang_conversions", 1);
 							qmod_count += 1;
 						}
 						for (whole, [lang, content]) in re_multilang_p2.captures_iter(&modified.clone()).map(|caps| caps.extract()) {
@@ -322,7 +325,7 @@ impl Action for LangSyntaxConverter {
 							new_content.push_str(&format!("[[lang code='{lang}']]{content}[[/lang]]"));
 							new_content.push_str(&modified[whole.len()+start..]);
 							modified = new_content;
-							self.multilang_conversions += 1;
+							counters.add("multilang_conversions", 1);
 							qmod_count += 1;
 						}
 
@@ -334,7 +337,7 @@ impl Action for LangSyntaxConverter {
 							new_content.push_str(&format!("[[lang code='{trimmedlang}']]{content}[[/lang]]"));
 							new_content.push_str(&modified[whole.len()+start..]);
 							modified = new_content;
-							self.mlang_conversions += 1;
+							counters.add("mlang_conversions", 1);
 							qmod_count += 1;
 						}
 
@@ -342,7 +345,8 @@ impl Action for LangSyntaxConverter {
 						match typeofuse {
 							StackStringUsage::CASText => {
 								if modified == *value {
-									self.saw_logic_fragments = true;
+									counters.add("logic_fragments", 1);
+									notes.push(Note::warning("Fragmented localisation syntax in logic, cannot fix that.".to_string()).at_level(3).about(target.clone()).of_kind("fragmented_logic"));
 								} else if target_inline_castext {
 									// Simply update the string, surely these are not escaped things?
 									// Balancing between edge cases is difficult.
@@ -357,7 +361,8 @@ impl Action for LangSyntaxConverter {
 							}
 							StackStringUsage::CASTextConcat => {
 								if modified == *value {
-									self.saw_logic_fragments = true;
+									counters.add("logic_fragments", 1);
+									notes.push(Note::warning("Fragmented localisation syntax in logic, cannot fix that.".to_string()).at_level(3).about(target.clone()).of_kind("fragmented_logic"));
 								} else if target_inline_castext {
 									// In a string argument of CASTextConcat!? 
 									// Odd but we can turn that to inline CASText.
@@ -372,10 +377,11 @@ impl Action for LangSyntaxConverter {
 							}
 							StackStringUsage::CompiledCASText(_) => {
 								if modified == *value {
-									self.saw_logic_fragments = true;
+									counters.add("logic_fragments", 1);
+									notes.push(Note::warning("Fragmented localisation syntax in logic, cannot fix that.".to_string()).at_level(3).about(target.clone()).of_kind("fragmented_logic"));
 								} else {
-									self.saw_oddities = true;
-									notes.push("   + Spotted localisation in something looking like externally compiled CASText. Not touching.".to_string());
+									counters.add("oddities", 1);
+									notes.push(Note::warning("Spotted localisation in something looking like externally compiled CASText. Not touching.".to_string()).at_level(3).about(target.clone()).of_kind("compiled_castext"));
 								}
 							}
 							StackStringUsage::Include | StackStringUsage::IncludeContrib => {
@@ -383,9 +389,10 @@ impl Action for LangSyntaxConverter {
 							}
 							StackStringUsage::ListElement(ind) => {
 								if modified == *value {
-									self.saw_logic_fragments = true;
+									counters.add("logic_fragments", 1);
+									notes.push(Note::warning("Fragmented localisation syntax in logic, cannot fix that.".to_string()).at_level(3).about(target.clone()).of_kind("fragmented_logic"));
 								} else if target_third_elements && *ind == 2 {
-									notes.push("   + Spotted a likely MCQ-option label, turning it to inline CASText, this might break things.".to_string());
+									notes.push(Note::warning("Spotted a likely MCQ-option label, turning it to inline CASText, this might break things.".to_string()).at_level(3).about(target.clone()).of_kind("mcq_label"));
 									let mut newstring: String = String::new();
 									newstring.push_str(&unwrapped[0..stringnode.position.startbyte]);
 									newstring.push_str("castext(\"");
@@ -394,14 +401,15 @@ impl Action for LangSyntaxConverter {
 									newstring.push_str(&unwrapped[stringnode.position.endbyte..unwrapped.len()]);
 									unwrapped = newstring;
 								} else {
-									notes.push("   + Spotted localisation in string not directly identified as a safe target.".to_string());
+									notes.push(Note::warning("Spotted localisation in string not directly identified as a safe target.".to_string()).at_level(3).about(target.clone()).of_kind("unsafe_target"));
 								}
 							}
 							StackStringUsage::Unknown => {
 								if modified == *value {
-									self.saw_logic_fragments = true;
+									counters.add("logic_fragments", 1);
+									notes.push(Note::warning("Fragmented localisation syntax in logic, cannot fix that.".to_string()).at_level(3).about(target.clone()).of_kind("fragmented_logic"));
 								} else {
-									notes.push("   + Spotted localisation in string not directly identified as a safe target.".to_string());
+									notes.push(Note::warning("Spotted localisation in string not directly identified as a safe target.".to_string()).at_level(3).about(target.clone()).of_kind("unsafe_target"));
 								}
 							}
 						}
@@ -410,27 +418,30 @@ impl Action for LangSyntaxConverter {
 
 				// Are there still bits with those.
 				if unwrapped.contains("mlang") || unwrapped.contains("multilang") {
-					notes.push("   + Localisation possibly used in areas not felt safe to modify.".to_string());
+					notes.push(Note::warning("Localisation possibly used in areas not felt safe to modify.".to_string()).at_level(3).about(target.clone()).of_kind("unsafe_area"));
 				}
 			}
 			// Did we change something?
 			if keyval.unwrap_cdata() != unwrapped {
+				if is_question_variables && !relocated.is_empty() {
+					relocated.push(pending.len());
+					self.linked.push(std::mem::take(&mut relocated));
+				}
 				let change: Change = Change::cdata_wrapped_version(keyval.clone(), unwrapped);
-				if write {
-					parser.register_change(change);
-				}	
+				pending.push(change);
 			}
 		}
 
 		if qmod_count > 0 {
 			if write {
-				notes.push(format!(" Converted {} other lang syntax uses to `[[lang]]`.", qmod_count));
+				notes.push(Note::info(format!("Converted {} other lang syntax uses to `[[lang]]`.", qmod_count)).of_kind("converted"));
 			} else {
-				notes.push(format!(" Could convert {} other lang syntax uses to `[[lang]]`.", qmod_count));
+				notes.push(Note::info(format!("Could convert {} other lang syntax uses to `[[lang]]`.", qmod_count)).of_kind("converted"));
 			}
 		}
 
-		(things_to_do, notes)
+		self.counters.merge(&counters);
+		Ok((pending, notes))
 	}
 
 	fn name(&self) -> String {
@@ -454,31 +465,43 @@ Tries to also fix MCQ-labels, but won't be too aggressive trying to convert
 		qtype == *"stack"
 	}
 
+	fn linked_changes(&self) -> Vec<Vec<usize>> {
+		self.linked.clone()
+	}
+
 	fn report(&self) -> Option<String> {
-		if self.multilang_conversions == 0 &&
-			self.mlang_conversions == 0 &&
-			!self.saw_mcq &&
-			!self.saw_logic_fragments &&
-			!self.saw_oddities {
+		if self.counters.get("multilang_conversions") == 0 &&
+			self.counters.get("mlang_conversions") == 0 &&
+			self.counters.get("mcq_oddities") == 0 &&
+			self.counters.get("logic_fragments") == 0 &&
+			self.counters.get("oddities") == 0 {
 			// Did nothing.
 			return None;
 		}
 		let mut result: String = String::new();
-		if self.multilang_conversions > 0 {
-			result.push_str(&format!("Could replace {} '<span class=\"multilang\">' uses.\n", self.multilang_conversions));
+		if self.counters.get("multilang_conversions") > 0 {
+			result.push_str(&format!("Could replace {} '<span class=\"multilang\">' uses.\n", self.counters.get("multilang_conversions")));
 		}
-		if self.mlang_conversions > 0 {
-			result.push_str(&format!("Could replace {} '{{mlang}}' uses.\n", self.mlang_conversions));
+		if self.counters.get("mlang_conversions") > 0 {
+			result.push_str(&format!("Could replace {} '{{mlang}}' uses.\n", self.counters.get("mlang_conversions")));
 		}
-		if self.saw_mcq {
+		if self.counters.get("mcq_oddities") > 0 {
 			result.push_str("Saw something odd in MCQ-inputs, check those.\n");
 		}
-		if self.saw_logic_fragments {
+		if self.counters.get("logic_fragments") > 0 {
 			result.push_str("Saw fragmented localisation syntax in logic, cannot fix that.\n");
 		}
-		if self.saw_oddities {
+		if self.counters.get("oddities") > 0 {
 			result.push_str("Saw truly odd, did not know what to do.\n");
 		}
 		Some(result)
 	}
-}
\ No newline at end of file
+
+	fn counters(&self) -> Counters {
+		self.counters.clone()
+	}
+
+	fn merge(&mut self, other: &dyn Action) {
+		self.counters.merge(&other.counters());
+	}
+}
@@ -0,0 +1,367 @@
+//! The command line model: paths, global options, the selected actions
+//! and the options of those actions.
+//!
+//! Anything starting with `--` must be a known global option, the flag of
+//! an action or an option of an action. Options taking values accept both
+//! `--name=value` and `--name value`.
+
+use crate::config::Config;
+use crate::journal::DEFAULT_JOURNAL_DIR;
+use crate::options::{OptionKind, OptionSpec, OptionValues};
+use crate::output::Format;
+use crate::status::Status;
+use std::collections::HashMap;
+
+/// The options that are not tied to any action.
+pub fn global_options() -> Vec<OptionSpec> {
+	vec![
+		OptionSpec::new("help", OptionKind::Flag,
+			"Describes actions in some more detail, `--help <action>` for a single one."),
+		OptionSpec::new("write", OptionKind::Flag,
+			"The general write flag to execute things not just report."),
+		OptionSpec::new("diff", OptionKind::Flag,
+			"Print a unified diff of what writing would change, without writing."),
+		OptionSpec::new("interactive", OptionKind::Flag,
+			"Show each change and ask whether to make it, then write the accepted ones."),
+		OptionSpec::new("stdout", OptionKind::Flag,
+			"Print the resulting XML of the single input to stdout instead of writing it, notes go to stderr."),
+		OptionSpec::new("watch", OptionKind::Flag,
+			"Keep watching the paths and check files again as they are saved, never writes."),
+		OptionSpec::new("backup", OptionKind::Flag,
+			"With --write, keep the original of each written file as <file>.bak."),
+		OptionSpec::new("journal", OptionKind::Flag,
+			"With --write, record the originals and the changes so that the run can be undone."),
+		OptionSpec::new("journal-dir", OptionKind::Text,
+			"Where the journals are kept.")
+			.default(DEFAULT_JOURNAL_DIR),
+		OptionSpec::new("undo", OptionKind::Text,
+			"Restore the files written in the given journal run, needs no actions or paths."),
+		OptionSpec::new("config", OptionKind::Text,
+			"Read this configuration file instead of looking for stack-cli-util.toml."),
+		OptionSpec::new("no-config", OptionKind::Flag,
+			"Ignore any configuration file."),
+		OptionSpec::new("include", OptionKind::Repeated,
+			"Only take matching files from directories, '*.xml' if not given."),
+		OptionSpec::new("exclude", OptionKind::Repeated,
+			"Skip matching files and directories, '.git' and manifests are always skipped."),
+		OptionSpec::new("only-name", OptionKind::Text,
+			"Only act on questions whose name matches this regular expression."),
+		OptionSpec::new("only-category", OptionKind::Repeated,
+			"Only act on questions in this category or below it, e.g. 'top/Algebra'."),
+		OptionSpec::new("only-type", OptionKind::Repeated,
+			"Only act on questions of these types, e.g. 'stack,multichoice'."),
+		OptionSpec::new("only-idnumber", OptionKind::Repeated,
+			"Only act on questions with these idnumbers, comma separated."),
+		OptionSpec::new("index", OptionKind::Text,
+			"Only act on questions at these positions in each file, e.g. '3..10', 1-based and inclusive."),
+		OptionSpec::new("changed-since", OptionKind::Text,
+			"Only process files changed since this git revision, uncommitted changes included."),
+		OptionSpec::new("changed-questions", OptionKind::Flag,
+			"With --changed-since, only act on the questions with changed lines."),
+		OptionSpec::new("baseline", OptionKind::Text,
+			"Only report findings not in this baseline file, see --write-baseline."),
+		OptionSpec::new("write-baseline", OptionKind::Flag,
+			"Record the current warnings and errors to the --baseline file, stack-cli-util-baseline.json if not given."),
+		OptionSpec::new("jobs", OptionKind::Number,
+			"How many files to process in parallel, 0 for one per CPU core.")
+			.default("1"),
+		OptionSpec::new("format", OptionKind::Choice(vec!["text".to_string(), "json".to_string(), "jsonl".to_string()]),
+			"How to print notes and reports, JSON Lines prints records as they come.")
+			.default("text"),
+		OptionSpec::new("fail-on", OptionKind::Choice(vec!["change".to_string(), "warning".to_string(), "error".to_string()]),
+			"The lowest outcome that gives a non-zero exit code.")
+			.default("error")
+	]
+}
+
+pub struct Arguments {
+	/// Files and directories to process.
+	pub paths: Vec<String>,
+	/// Flags of the selected actions, in the order given.
+	pub actions: Vec<String>,
+	/// The action `--help` was asked about, if any.
+	pub help_topic: Option<String>,
+	pub global: OptionValues,
+	action_options: HashMap<String, OptionValues>
+}
+
+impl Arguments {
+	/// Parses the arguments, excluding the program name. The actions are
+	/// given as their flags and option declarations. The configuration, if
+	/// any, fills in what the arguments leave open.
+	pub fn parse(args: &[String], actions: &[(String, Vec<OptionSpec>)], config: Option<&Config>) -> Result<Arguments, String> {
+		let globals: Vec<OptionSpec> = global_options();
+		let mut result = Arguments {
+			paths: Vec::new(),
+			actions: Vec::new(),
+			help_topic: None,
+			global: OptionValues::new(),
+			action_options: HashMap::new()
+		};
+		for (flag, _) in actions {
+			result.action_options.insert(flag.clone(), OptionValues::new());
+		}
+
+		let mut i: usize = 0;
+		while i < args.len() {
+			let arg: &String = &args[i];
+			i += 1;
+			if arg == "--" {
+				// Everything after is a path, even if it looks like an option.
+				result.paths.extend(args[i..].iter().cloned());
+				break;
+			}
+			let body: &str = match arg.strip_prefix("--") {
+				Some(body) => body,
+				None => {
+					result.paths.push(arg.clone());
+					continue;
+				}
+			};
+			let (name, inline_value): (&str, Option<&str>) = match body.split_once('=') {
+				Some((name, value)) => (name, Some(value)),
+				None => (body, None)
+			};
+
+			if let Some(spec) = globals.iter().find(|s| s.name == name) {
+				let value = Arguments::take_value(spec, name, inline_value, args, &mut i)?;
+				result.global.set(spec, spec.parse_value(&value, name)?);
+				if name == "help" && i < args.len() && actions.iter().any(|(flag, _)| *flag == args[i]) {
+					result.help_topic = Some(args[i].clone());
+					i += 1;
+				}
+			} else if actions.iter().any(|(flag, _)| flag == name) {
+				if inline_value.is_some() {
+					return Err(format!("The action flag --{name} takes no value, its options are given as --{name}-<option>."));
+				}
+				if !result.actions.iter().any(|a| a == name) {
+					result.actions.push(name.to_string());
+				}
+			} else {
+				let mut found: bool = false;
+				for (flag, specs) in actions {
+					let option: &str = match name.strip_prefix(flag.as_str()).and_then(|r| r.strip_prefix('-')) {
+						Some(option) => option,
+						None => continue
+					};
+					if let Some(spec) = specs.iter().find(|s| s.name == option) {
+						let value = Arguments::take_value(spec, name, inline_value, args, &mut i)?;
+						let parsed = spec.parse_value(&value, name)?;
+						if let Some(values) = result.action_options.get_mut(flag) {
+							values.set(spec, parsed);
+						}
+						found = true;
+						break;
+					}
+				}
+				if !found {
+					return Err(format!("Unknown option '--{name}', see --help for the known ones."));
+				}
+			}
+		}
+
+		if let Some(config) = config {
+			let known: Vec<String> = actions.iter().map(|(flag, _)| flag.clone()).collect();
+			let unknown: Vec<String> = config.unknown_actions(&known);
+			if !unknown.is_empty() {
+				return Err(format!("In '{}': unknown actions {}.", config.path.display(), unknown.join(", ")));
+			}
+			if result.actions.is_empty() {
+				result.actions = config.actions.clone();
+			}
+			config.apply_options(&mut result.global, &globals)?;
+			for (flag, specs) in actions {
+				if let Some(values) = result.action_options.get_mut(flag) {
+					config.apply_action_options(flag, values, specs)?;
+				}
+			}
+		}
+
+		result.global.fill_defaults(&globals);
+		for (flag, specs) in actions {
+			if let Some(values) = result.action_options.get_mut(flag) {
+				values.fill_defaults(specs);
+			}
+		}
+		Ok(result)
+	}
+
+	/// Picks the value for an option, either from after the `=` or from the
+	/// next argument.
+	fn take_value(spec: &OptionSpec, name: &str, inline_value: Option<&str>, args: &[String], i: &mut usize) -> Result<String, String> {
+		if !spec.takes_value() {
+			if inline_value.is_some() {
+				return Err(format!("--{name} takes no value."));
+			}
+			return Ok(String::new());
+		}
+		match inline_value {
+			Some(value) => Ok(value.to_string()),
+			None => {
+				if *i < args.len() && !args[*i].starts_with("--") {
+					*i += 1;
+					Ok(args[*i - 1].clone())
+				} else {
+					Err(format!("--{name} needs a value."))
+				}
+			}
+		}
+	}
+
+	pub fn help(&self) -> bool {
+		self.global.flag("help")
+	}
+
+	pub fn write(&self) -> bool {
+		self.global.flag("write")
+	}
+
+	pub fn diff(&self) -> bool {
+		self.global.flag("diff")
+	}
+
+	pub fn interactive(&self) -> bool {
+		self.global.flag("interactive")
+	}
+
+	pub fn stdout(&self) -> bool {
+		self.global.flag("stdout")
+	}
+
+	pub fn watch(&self) -> bool {
+		self.global.flag("watch")
+	}
+
+	pub fn backup(&self) -> bool {
+		self.global.flag("backup")
+	}
+
+	pub fn journal(&self) -> bool {
+		self.global.flag("journal")
+	}
+
+	pub fn journal_dir(&self) -> String {
+		self.global.text("journal-dir").unwrap_or_else(|| DEFAULT_JOURNAL_DIR.to_string())
+	}
+
+	/// The run-id given to `--undo`.
+	pub fn undo(&self) -> Option<String> {
+		self.global.text("undo")
+	}
+
+	/// The git revision given to `--changed-since`.
+	pub fn changed_since(&self) -> Option<String> {
+		self.global.text("changed-since")
+	}
+
+	pub fn changed_questions(&self) -> bool {
+		self.global.flag("changed-questions")
+	}
+
+	pub fn baseline(&self) -> Option<String> {
+		self.global.text("baseline")
+	}
+
+	pub fn write_baseline(&self) -> bool {
+		self.global.flag("write-baseline")
+	}
+
+	/// The number of worker threads, at least one.
+	pub fn jobs(&self) -> usize {
+		match self.global.number("jobs") {
+			Some(0) => std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+			Some(jobs) => jobs,
+			None => 1
+		}
+	}
+
+	pub fn format(&self) -> Format {
+		self.global.text("format").and_then(|f| Format::from_name(&f)).unwrap_or(Format::Text)
+	}
+
+	pub fn fail_on(&self) -> Status {
+		self.global.text("fail-on").and_then(|f| Status::fail_on_from_name(&f)).unwrap_or(Status::Error)
+	}
+
+	pub fn selected(&self, flag: &str) -> bool {
+		self.actions.iter().any(|a| a == flag)
+	}
+
+	/// The options given to an action, with defaults filled in.
+	pub fn action_options(&self, flag: &str) -> OptionValues {
+		self.action_options.get(flag).cloned().unwrap_or_default()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Arguments;
+	use crate::options::{OptionKind, OptionSpec};
+
+	fn actions() -> Vec<(String, Vec<OptionSpec>)> {
+		vec![
+			("files".to_string(), vec![
+				OptionSpec::new("move", OptionKind::Flag, "Move files."),
+				OptionSpec::new("limit", OptionKind::Number, "At most this many.").default("5")
+			]),
+			("stacklang".to_string(), Vec::new())
+		]
+	}
+
+	fn parse(args: &[&str]) -> Result<Arguments, String> {
+		let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+		Arguments::parse(&args, &actions(), None)
+	}
+
+	#[test]
+	fn paths_actions_and_their_options_are_told_apart() {
+		let args: Arguments = parse(&["a.xml", "--stacklang", "--files", "--files-move", "--files-limit=2", "b.xml"]).unwrap();
+		assert_eq!(args.paths, vec!["a.xml", "b.xml"]);
+		assert_eq!(args.actions, vec!["stacklang", "files"]);
+		assert!(args.action_options("files").flag("move"));
+		assert_eq!(args.action_options("files").number("limit"), Some(2));
+		assert!(args.selected("files"));
+	}
+
+	#[test]
+	fn values_may_follow_as_the_next_argument() {
+		let args: Arguments = parse(&["--files", "--files-limit", "3", "--jobs", "4", "a.xml"]).unwrap();
+		assert_eq!(args.action_options("files").number("limit"), Some(3));
+		assert_eq!(args.jobs(), 4);
+		assert_eq!(args.paths, vec!["a.xml"]);
+	}
+
+	#[test]
+	fn defaults_fill_what_is_not_given() {
+		let args: Arguments = parse(&["--files"]).unwrap();
+		assert_eq!(args.action_options("files").number("limit"), Some(5));
+		assert!(!args.action_options("files").flag("move"));
+		assert_eq!(args.jobs(), 1);
+		assert!(!args.write());
+	}
+
+	#[test]
+	fn everything_after_a_double_dash_is_a_path() {
+		let args: Arguments = parse(&["--write", "--", "--files", "a.xml"]).unwrap();
+		assert!(args.write());
+		assert!(args.actions.is_empty());
+		assert_eq!(args.paths, vec!["--files", "a.xml"]);
+	}
+
+	#[test]
+	fn help_takes_an_action_as_its_topic() {
+		let args: Arguments = parse(&["--help", "files"]).unwrap();
+		assert!(args.help());
+		assert_eq!(args.help_topic.as_deref(), Some("files"));
+		assert!(args.paths.is_empty());
+	}
+
+	#[test]
+	fn mistakes_are_explained() {
+		assert_eq!(parse(&["--nonsense"]).err().unwrap(), "Unknown option '--nonsense', see --help for the known ones.");
+		assert_eq!(parse(&["--files=yes"]).err().unwrap(), "The action flag --files takes no value, its options are given as --files-<option>.");
+		assert_eq!(parse(&["--write=yes"]).err().unwrap(), "--write takes no value.");
+		assert_eq!(parse(&["--files", "--files-limit"]).err().unwrap(), "--files-limit needs a value.");
+		assert_eq!(parse(&["--jobs", "--write"]).err().unwrap(), "--jobs needs a value.");
+	}
+}
@@ -0,0 +1,249 @@
+//! Known findings to leave out of the reports, so that only new ones show
+//! up and fail CI.
+//!
+//! A finding is a note of warning or error severity, identified by the
+//! file, the question (its idnumber, or name if it has none), the action,
+//! the kind of the note and its target. Messages are not part of that as
+//! they may change between versions.
+
+use crate::action::Severity;
+use crate::runner::FileResult;
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_BASELINE_FILE: &str = "stack-cli-util-baseline.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Finding {
+	file: String,
+	question: String,
+	action: String,
+	kind: String,
+	target: String
+}
+
+/// Findings of an earlier run.
+pub struct Baseline {
+	/// Where this is stored, file names are relative to its directory.
+	path: PathBuf,
+	findings: BTreeSet<Finding>,
+	/// Those seen in this run.
+	seen: HashSet<Finding>,
+	/// Files processed in this run.
+	files: HashSet<String>
+}
+
+impl Baseline {
+	/// An empty one, to be filled with `record` and written with `save`.
+	pub fn new(path: &Path) -> Baseline {
+		Baseline {
+			path: path.to_path_buf(),
+			findings: BTreeSet::new(),
+			seen: HashSet::new(),
+			files: HashSet::new()
+		}
+	}
+
+	/// Where this is stored.
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	pub fn load(path: &Path) -> Result<Baseline, String> {
+		let content: String = std::fs::read_to_string(path)
+			.map_err(|e| format!("Could not read baseline '{}': {}", path.display(), e))?;
+		let data: Value = serde_json::from_str(&content)
+			.map_err(|e| format!("Could not parse baseline '{}': {}", path.display(), e))?;
+		let mut baseline: Baseline = Baseline::new(path);
+		for entry in data["findings"].as_array().cloned().unwrap_or_default() {
+			let field = |name: &str| entry[name].as_str().unwrap_or_default().to_string();
+			baseline.findings.insert(Finding {
+				file: field("file"),
+				question: field("question"),
+				action: field("action"),
+				kind: field("kind"),
+				target: field("target")
+			});
+		}
+		Ok(baseline)
+	}
+
+	pub fn save(&self) -> Result<(), String> {
+		let findings: Vec<Value> = self.findings.iter().map(|f| json!({
+			"file": f.file,
+			"question": f.question,
+			"action": f.action,
+			"kind": f.kind,
+			"target": f.target
+		})).collect();
+		let content: String = serde_json::to_string_pretty(&json!({
+			"findings": findings
+		})).unwrap_or_default();
+		crate::save::write_atomic(&self.path.to_string_lossy(), &format!("{content}\n"))
+	}
+
+	/// The file name as stored, relative to the directory of the baseline
+	/// so that it does not matter where the tool is run from.
+	fn file_key(&self, file: &str) -> String {
+		let base: Option<PathBuf> = self.path.parent()
+			.map(|p| if p.as_os_str().is_empty() { Path::new(".") } else { p })
+			.and_then(|p| std::fs::canonicalize(p).ok());
+		match (base, std::fs::canonicalize(file)) {
+			(Some(base), Ok(full)) => match full.strip_prefix(&base) {
+				Ok(relative) => relative.to_string_lossy().to_string(),
+				Err(_) => full.to_string_lossy().to_string()
+			},
+			_ => file.to_string()
+		}
+	}
+
+	fn findings_of(&self, result: &FileResult) -> Vec<(usize, usize, Finding)> {
+		let file: String = self.file_key(&result.file);
+		let mut found: Vec<(usize, usize, Finding)> = Vec::new();
+		for (qi, question) in result.questions.iter().enumerate() {
+			for (ni, note) in question.notes.iter().enumerate() {
+				if note.note.severity < Severity::Warning {
+					continue;
+				}
+				found.push((qi, ni, Finding {
+					file: file.clone(),
+					question: question.idnumber.clone().unwrap_or_else(|| question.name.clone()),
+					action: note.action.clone(),
+					kind: note.note.kind.clone().unwrap_or_default(),
+					target: note.note.target.clone().unwrap_or_default()
+				}));
+			}
+		}
+		found
+	}
+
+	/// Adds the findings of a file.
+	pub fn record(&mut self, result: &FileResult) {
+		for (_, _, finding) in self.findings_of(result) {
+			self.findings.insert(finding);
+		}
+	}
+
+	/// Drops the known findings from the result, returns how many.
+	pub fn filter(&mut self, result: &mut FileResult) -> usize {
+		self.files.insert(self.file_key(&result.file));
+		let mut known: Vec<(usize, usize)> = Vec::new();
+		for (qi, ni, finding) in self.findings_of(result) {
+			if self.findings.contains(&finding) {
+				known.push((qi, ni));
+				self.seen.insert(finding);
+			}
+		}
+		// Backwards so that the indices stay valid.
+		for (qi, ni) in known.iter().rev() {
+			result.questions[*qi].notes.remove(*ni);
+		}
+		known.len()
+	}
+
+	/// Findings of the processed files that were not seen anymore, i.e.
+	/// fixed ones that could be dropped from the baseline.
+	pub fn stale(&self) -> usize {
+		self.findings.iter().filter(|f| self.files.contains(&f.file) && !self.seen.contains(*f)).count()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Baseline;
+	use crate::action::Note;
+	use crate::runner::{ActionNote, FileResult, QuestionResult};
+	use std::path::Path;
+
+	fn question(name: &str, idnumber: Option<&str>, notes: Vec<(&str, Note)>) -> QuestionResult {
+		QuestionResult {
+			index: 1,
+			name: name.to_string(),
+			qtype: "stack".to_string(),
+			category: None,
+			idnumber: idnumber.map(|i| i.to_string()),
+			notes: notes.into_iter().map(|(action, note)| ActionNote {
+				action: action.to_string(),
+				note
+			}).collect(),
+			changed: false,
+			failure: None
+		}
+	}
+
+	fn file(name: &str, questions: Vec<QuestionResult>) -> FileResult {
+		FileResult {
+			file: name.to_string(),
+			question_count: questions.len(),
+			questions,
+			original: String::new(),
+			modified: None,
+			conflicts: Vec::new()
+		}
+	}
+
+	fn missing(message: &str, target: &str) -> Note {
+		Note::warning(message.to_string()).of_kind("missing_file").about(target.to_string())
+	}
+
+	fn messages(result: &FileResult) -> Vec<String> {
+		result.questions.iter().flat_map(|q| q.notes.iter().map(|n| n.note.message.clone())).collect()
+	}
+
+	/// A baseline that does not exist on disk, so file names are kept as
+	/// given.
+	fn baseline(result: &FileResult) -> Baseline {
+		let mut baseline = Baseline::new(Path::new("/nonexistent/baseline.json"));
+		baseline.record(result);
+		baseline
+	}
+
+	#[test]
+	fn known_findings_match_whatever_their_message() {
+		let mut baseline = baseline(&file("a.xml", vec![question("Q", Some("q1"), vec![("files", missing("Old message.", "/a.png"))])]));
+		let mut result = file("a.xml", vec![question("Renamed", Some("q1"), vec![
+			("files", missing("New message.", "/a.png")),
+			("files", missing("Another file.", "/b.png"))
+		])]);
+		assert_eq!(baseline.filter(&mut result), 1);
+		assert_eq!(messages(&result), vec!["Another file."]);
+		assert_eq!(baseline.stale(), 0);
+	}
+
+	#[test]
+	fn every_part_of_the_key_counts() {
+		let recorded = file("a.xml", vec![question("Q", None, vec![("files", missing("Missing.", "/a.png"))])]);
+		let others: Vec<FileResult> = vec![
+			file("b.xml", vec![question("Q", None, vec![("files", missing("Missing.", "/a.png"))])]),
+			file("a.xml", vec![question("Other", None, vec![("files", missing("Missing.", "/a.png"))])]),
+			file("a.xml", vec![question("Q", None, vec![("dupfiles", missing("Missing.", "/a.png"))])]),
+			file("a.xml", vec![question("Q", None, vec![("files", Note::warning("Missing.".to_string()).of_kind("unused_file").about("/a.png".to_string()))])]),
+			file("a.xml", vec![question("Q", None, vec![("files", missing("Missing.", "/b.png"))])])
+		];
+		for mut other in others {
+			let mut baseline = baseline(&recorded);
+			assert_eq!(baseline.filter(&mut other), 0);
+			assert_eq!(messages(&other).len(), 1);
+		}
+		// Without an idnumber the name identifies the question.
+		let mut same = file("a.xml", vec![question("Q", None, vec![("files", missing("Missing.", "/a.png"))])]);
+		assert_eq!(baseline(&recorded).filter(&mut same), 1);
+	}
+
+	#[test]
+	fn only_warnings_and_errors_are_recorded() {
+		let info = Note::info("Could delete.".to_string()).of_kind("unused_file");
+		let mut baseline = baseline(&file("a.xml", vec![question("Q", None, vec![("files", info.clone())])]));
+		let mut result = file("a.xml", vec![question("Q", None, vec![("files", info)])]);
+		assert_eq!(baseline.filter(&mut result), 0);
+	}
+
+	#[test]
+	fn findings_not_seen_again_are_stale_in_processed_files_only() {
+		let mut baseline = baseline(&file("a.xml", vec![question("Q", None, vec![("files", missing("Missing.", "/a.png"))])]));
+		baseline.record(&file("b.xml", vec![question("Q", None, vec![("files", missing("Missing.", "/a.png"))])]));
+		baseline.filter(&mut file("a.xml", vec![question("Q", None, Vec::new())]));
+		assert_eq!(baseline.stale(), 1);
+	}
+}
@@ -0,0 +1,476 @@
+//! Collecting the changes actions want to make so that they can be checked
+//! against each other and handed to the parser in one go.
+//!
+//! The parser panics when given overlapping changes, so everything is
+//! checked here first and nothing is registered if anything overlaps.
+
+use position_preserving_moodle_question_xml_edit::{Change, ContentRef, QParser, Question};
+use crate::panics;
+
+/// Cannot appear in XML, so it marks where a change went in a copy of the
+/// document.
+const MARKER: char = '\u{0}';
+
+/// Stands in for the questions in a small copy of the document that is
+/// cheap to take from version to version.
+const PROBE: &str = "<question type=\"probe\"><name><text>probe</text></name></question>";
+
+/// A document at some version of the parser, for working out where changes
+/// made against that version are. The parser keeps the positions of its
+/// references to itself, so the changes are made on a copy of the document
+/// with markers as their content, and the ranges read from where the
+/// markers end up.
+struct Snapshot {
+	content: String,
+	version: usize,
+	/// The bytes before the first question and after the last one. The
+	/// copies start as those around `PROBE`, which takes little to parse,
+	/// and get the questions back in their last change.
+	around: Option<(usize, usize)>
+}
+
+impl Snapshot {
+	/// Executes the changes the parser has waiting and takes the result.
+	fn of(parser: &mut QParser) -> Snapshot {
+		let reference: Option<ContentRef> = panics::catch(|| parser.find_questions())
+			.ok()
+			.and_then(|questions| questions.into_iter().next())
+			.map(|q| q.whole_element);
+		let content: String = parser.get_current_content();
+		let around: Option<(usize, usize)> = questions_between(&content);
+		let mut snapshot = Snapshot {
+			content,
+			version: 0,
+			around
+		};
+		if let Some(reference) = reference {
+			snapshot.version = snapshot.version_of(&reference).unwrap_or_default();
+		}
+		snapshot
+	}
+
+	/// The version `reference` comes from, taking the small copy forward
+	/// until it accepts a change to it. Versions only grow, so it will.
+	fn version_of(&self, reference: &ContentRef) -> Option<usize> {
+		let mut small: QParser = self.small()?;
+		let mut version: usize = 0;
+		// The parser checks the version before anything else, so a refused
+		// change leaves the copy as it was.
+		while panics::catch(|| small.register_change(Change::new(reference.clone(), String::new()))).is_err() {
+			bump(&mut small)?;
+			version += 1;
+		}
+		Some(version)
+	}
+
+	/// A copy of the document at its version, nothing if it cannot be had.
+	fn copy(&self) -> Option<QParser> {
+		if self.version == 0 {
+			return QParser::from_string(self.content.clone()).ok();
+		}
+		let (before, after) = self.around?;
+		panics::catch(|| -> Option<QParser> {
+			let mut copy: QParser = self.small_at(self.version - 1)?;
+			let probe: Question = copy.find_questions().into_iter().next()?;
+			copy.register_change(Change::new(probe.whole_element, self.content[before..after].to_string()));
+			copy.execute_changes();
+			Some(copy)
+		}).ok()?
+	}
+
+	/// The document with `PROBE` for its questions.
+	fn small(&self) -> Option<QParser> {
+		let (before, after) = self.around?;
+		let content: String = format!("{}{PROBE}{}", &self.content[..before], &self.content[after..]);
+		QParser::from_string(content).ok()
+	}
+
+	/// The small document taken to `version`.
+	fn small_at(&self, version: usize) -> Option<QParser> {
+		let mut small: QParser = self.small()?;
+		for _ in 0..version {
+			bump(&mut small)?;
+		}
+		Some(small)
+	}
+
+	/// The ranges of `positions`, made against this version, in `content`.
+	/// Any that the copy refuses, e.g. one from an earlier version, are
+	/// taken to cover the whole document, so that they conflict with any
+	/// other change rather than slip through.
+	fn locate(&self, positions: &[&ContentRef]) -> Vec<(usize, usize)> {
+		if positions.is_empty() {
+			return Vec::new();
+		}
+		// All at once, unless some overlap or do not check out.
+		let together: Option<Vec<(usize, usize)>> = self.probe(positions)
+			.filter(|ranges| ranges.iter().zip(positions).all(|(r, p)| self.content.get(r.0..r.1) == Some(p.content.as_str())));
+		if let Some(ranges) = together {
+			return ranges;
+		}
+		positions.iter()
+			.map(|p| self.probe(&[*p]).map(|r| r[0]).unwrap_or((0, usize::MAX)))
+			.collect()
+	}
+
+	/// Makes the changes on a copy and reads their ranges from the result.
+	/// Nothing if the copy refuses them.
+	fn probe(&self, positions: &[&ContentRef]) -> Option<Vec<(usize, usize)>> {
+		let content: &str = &self.content;
+		let mut copy: QParser = self.copy()?;
+		let marked: String = panics::catch(|| {
+			for (i, position) in positions.iter().enumerate() {
+				copy.register_change(Change::new((*position).clone(), format!("{MARKER}{i}{MARKER}")));
+			}
+			copy.execute_changes();
+			copy.get_current_content()
+		}).ok()?;
+
+		let mut ranges: Vec<Option<(usize, usize)>> = vec![None; positions.len()];
+		// How much longer the marked document is up to the current marker.
+		let mut shift: isize = 0;
+		let mut rest: usize = 0;
+		while let Some(found) = marked[rest..].find(MARKER) {
+			let at: usize = rest + found;
+			let length: usize = marked[at + 1..].find(MARKER)? + 2;
+			let i: usize = marked[at + 1..at + length - 1].parse().ok()?;
+			let replaced: usize = if positions.len() == 1 {
+				(content.len() + length).checked_sub(marked.len())?
+			} else {
+				positions.get(i)?.content.len()
+			};
+			let start: usize = usize::try_from(at as isize - shift).ok()?;
+			*ranges.get_mut(i)? = Some((start, start + replaced));
+			shift += length as isize - replaced as isize;
+			rest = at + length;
+		}
+		ranges.into_iter().collect()
+	}
+}
+
+/// Takes the parser to its next version with a change that changes
+/// nothing.
+fn bump(parser: &mut QParser) -> Option<()> {
+	panics::catch(|| -> Option<()> {
+		let first: Question = parser.find_questions().into_iter().next()?;
+		parser.register_change(Change::new(first.whole_element.clone(), first.whole_element.content));
+		parser.execute_changes();
+		Some(())
+	}).ok()?
+}
+
+/// Where the first `<question>` element of `content` starts and the last
+/// one ends.
+fn questions_between(content: &str) -> Option<(usize, usize)> {
+	let options = roxmltree::ParsingOptions {
+		allow_dtd: true,
+		..roxmltree::ParsingOptions::default()
+	};
+	let doc = roxmltree::Document::parse_with_options(content, options).ok()?;
+	let mut questions = doc.descendants().filter(|n| n.has_tag_name("question"));
+	let first = questions.next()?;
+	let last = questions.next_back().unwrap_or(first);
+	Some((first.range().start, last.range().end))
+}
+
+/// The `<question>` element the parser gives the index `index`, counting
+/// the questions that are not categories.
+pub fn question_node<'a, 'input>(doc: &'a roxmltree::Document<'input>, index: usize) -> Option<roxmltree::Node<'a, 'input>> {
+	doc.descendants()
+		.filter(|n| n.has_tag_name("question") && n.attribute("type").is_some_and(|t| t != "category"))
+		.nth(index)
+}
+
+/// Would the parser refuse to have these two at the same time. Mirrors its
+/// check, with the addition that two insertions at the same point clash as
+/// their order would be arbitrary.
+fn overlaps(a: (usize, usize), b: (usize, usize)) -> bool {
+	(b.0 >= a.0 && b.0 < a.1) || (b.1 > a.0 && b.1 <= a.1)
+		|| (a.0 >= b.0 && a.0 < b.1) || (a.1 > b.0 && a.1 <= b.1)
+		|| a == b
+}
+
+/// A change and who wants it.
+pub struct PendingChange {
+	/// Flag of the action.
+	pub action: String,
+	/// 1-based index of the question.
+	pub question: usize,
+	pub change: Change,
+	/// Byte range of the change in the document it was made against.
+	pub range: (usize, usize),
+	/// Changes that only work together share this, the others have one of
+	/// their own.
+	pub group: usize
+}
+
+/// One side of a conflict.
+#[derive(Debug, Clone)]
+pub struct ConflictSide {
+	/// Flag of the action.
+	pub action: String,
+	/// Where in the question the change was, e.g. "input[ans1]/tans".
+	pub field: String,
+	/// Byte range in the document the changes were made against. That is
+	/// the one loaded, unless the changes of actions others run after were
+	/// made first.
+	pub range: (usize, usize)
+}
+
+/// Two changes that cannot both be made.
+#[derive(Debug, Clone)]
+pub struct Conflict {
+	/// 1-based index of the question.
+	pub question: usize,
+	pub first: ConflictSide,
+	pub second: ConflictSide,
+	/// Is one inside the other rather than partly overlapping.
+	pub nested: bool
+}
+
+impl Conflict {
+	pub fn message(&self) -> String {
+		let (first, second) = (self.first.range, self.second.range);
+		let how: &str = if !self.nested {
+			"overlaps"
+		} else if first.0 <= second.0 && second.1 <= first.1 {
+			"is inside"
+		} else {
+			"contains"
+		};
+		format!("The change of --{} to '{}' {} the change of --{} to '{}'.",
+			self.second.action, self.second.field, how, self.first.action, self.first.field)
+	}
+}
+
+/// The changes of a file, waiting to be registered.
+pub struct ChangeSet {
+	changes: Vec<PendingChange>,
+	/// How many groups have been handed out.
+	groups: usize,
+	/// The document the changes are made against.
+	snapshot: Snapshot
+}
+
+impl ChangeSet {
+	/// For changes to the document of `parser`, executing those it has
+	/// waiting.
+	pub fn new(parser: &mut QParser) -> ChangeSet {
+		ChangeSet {
+			changes: Vec::new(),
+			groups: 0,
+			snapshot: Snapshot::of(parser)
+		}
+	}
+
+	/// Takes the changes, working out where in the document they are.
+	/// `linked` lists those that only work together, as indices to them.
+	pub fn add(&mut self, action: &str, question: usize, changes: Vec<Change>, linked: &[Vec<usize>]) {
+		let positions: Vec<&ContentRef> = changes.iter().map(|c| &c.position).collect();
+		let ranges: Vec<(usize, usize)> = self.snapshot.locate(&positions);
+		let mut groups: Vec<Option<usize>> = vec![None; changes.len()];
+		for group in linked {
+			for i in group {
+				if let Some(slot) = groups.get_mut(*i) {
+					*slot = Some(self.groups);
+				}
+			}
+			self.groups += 1;
+		}
+		for ((change, range), group) in changes.into_iter().zip(ranges).zip(groups) {
+			let group: usize = group.unwrap_or_else(|| {
+				self.groups += 1;
+				self.groups - 1
+			});
+			self.changes.push(PendingChange {
+				action: action.to_string(),
+				question,
+				change,
+				range,
+				group
+			});
+		}
+	}
+
+	/// The document the ranges of the changes are in.
+	pub fn content(&self) -> &str {
+		&self.snapshot.content
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = &PendingChange> {
+		self.changes.iter()
+	}
+
+	/// Keeps only the changes for which `keep` says so.
+	pub fn retain<F>(&mut self, keep: F)
+	where F: FnMut(&PendingChange) -> bool {
+		self.changes.retain(keep);
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.changes.is_empty()
+	}
+
+	/// Pairs of overlapping changes, as indices to the changes.
+	fn overlapping(&self) -> Vec<(usize, usize)> {
+		let mut result: Vec<(usize, usize)> = Vec::new();
+		for i in 0..self.changes.len() {
+			for j in (i + 1)..self.changes.len() {
+				if overlaps(self.changes[i].range, self.changes[j].range) {
+					result.push((i, j));
+				}
+			}
+		}
+		result
+	}
+
+	pub fn has_conflicts(&self) -> bool {
+		!self.overlapping().is_empty()
+	}
+
+	/// All pairs of changes that overlap.
+	pub fn conflicts(&self) -> Vec<Conflict> {
+		self.overlapping().into_iter().map(|(i, j)| {
+			let side = |c: &PendingChange| ConflictSide {
+				action: c.action.clone(),
+				field: field_at(self.content(), c.range),
+				range: c.range
+			};
+			let (first, second) = (side(&self.changes[i]), side(&self.changes[j]));
+			let contains = |a: (usize, usize), b: (usize, usize)| a.0 <= b.0 && b.1 <= a.1;
+			Conflict {
+				question: self.changes[i].question,
+				nested: contains(first.range, second.range) || contains(second.range, first.range),
+				first,
+				second
+			}
+		}).collect()
+	}
+
+	/// Makes all the changes in the parser, or none of them if some
+	/// overlap. After that the set is empty and takes changes to the new
+	/// version of the document.
+	pub fn register(&mut self, parser: &mut QParser) -> Result<(), Vec<Conflict>> {
+		if self.has_conflicts() {
+			return Err(self.conflicts());
+		}
+		if self.changes.is_empty() {
+			return Ok(());
+		}
+		for pending in self.changes.drain(..) {
+			parser.register_change(pending.change);
+		}
+		parser.execute_changes();
+		self.snapshot.content = parser.get_current_content();
+		self.snapshot.version += 1;
+		self.snapshot.around = questions_between(&self.snapshot.content);
+		Ok(())
+	}
+}
+
+/// Names the spot of a byte range inside its question, by the path of
+/// elements from the question down to the innermost one containing it.
+/// Elements with names, e.g. inputs, PRTs and files, get those in brackets
+/// and attribute values their attribute, e.g. "input[ans1]/tans" or
+/// "questiontext/file[a.png]@path".
+pub fn field_at(content: &str, range: (usize, usize)) -> String {
+	let options = roxmltree::ParsingOptions {
+		allow_dtd: true,
+		..roxmltree::ParsingOptions::default()
+	};
+	let doc = match roxmltree::Document::parse_with_options(content, options) {
+		Ok(doc) => doc,
+		Err(_) => return format!("bytes {}..{}", range.0, range.1)
+	};
+	let mut path: Vec<String> = Vec::new();
+	let mut node = doc.root_element();
+	loop {
+		let inner = node.children().find(|c| c.is_element() && c.range().start <= range.0 && range.1 <= c.range().end);
+		let child = match inner {
+			Some(child) => child,
+			None => break
+		};
+		// The path starts below the question.
+		if !path.is_empty() || node.has_tag_name("question") {
+			path.push(step_name(child));
+		}
+		node = child;
+	}
+	if let Some(attribute) = node.attributes().find(|a| a.range().start <= range.0 && range.1 <= a.range().end) {
+		path.push(format!("@{}", attribute.name()));
+	}
+	if path.is_empty() {
+		if node.has_tag_name("question") {
+			return "question".to_string();
+		}
+		return format!("bytes {}..{}", range.0, range.1);
+	}
+	path.join("/").replace("/@", "@")
+}
+
+/// The path of an element below its question, named like in `field_at`,
+/// e.g. "prt[prt1]/node[0]/truefeedback".
+pub fn element_path(node: roxmltree::Node) -> String {
+	let mut path: Vec<String> = Vec::new();
+	let mut current = Some(node);
+	while let Some(element) = current.filter(|n| n.is_element() && !n.has_tag_name("question")) {
+		path.push(step_name(element));
+		current = element.parent_element();
+	}
+	path.reverse();
+	path.join("/")
+}
+
+/// An element as a step of a path, with its name if it has one. Unnamed
+/// ones with siblings of the same sort, e.g. the answers of a multiple
+/// choice question, get their 1-based position among those instead, e.g.
+/// "answer[2]".
+fn step_name(element: roxmltree::Node) -> String {
+	let tag: &str = element.tag_name().name();
+	let mut step: String = tag.to_string();
+	let name: Option<String> = element.attribute("name").map(|n| n.to_string())
+		.or_else(|| element.children().find(|c| c.has_tag_name("name")).and_then(|n| n.text()).map(|n| n.trim().to_string()));
+	if let Some(name) = name.filter(|n| !n.is_empty() && tag != "text") {
+		step.push_str(&format!("[{name}]"));
+	} else if let Some(parent) = element.parent_element() {
+		let alike: Vec<roxmltree::Node> = parent.children().filter(|c| c.has_tag_name(tag)).collect();
+		if alike.len() > 1 {
+			let position: usize = alike.iter().position(|c| *c == element).unwrap_or_default() + 1;
+			step.push_str(&format!("[{position}]"));
+		}
+	}
+	step
+}
+
+#[cfg(test)]
+mod tests {
+	use super::overlaps;
+
+	#[test]
+	fn ranges_sharing_bytes_overlap() {
+		assert!(overlaps((0, 10), (5, 15)));
+		assert!(overlaps((5, 15), (0, 10)));
+		// One inside the other, either way round.
+		assert!(overlaps((0, 10), (2, 8)));
+		assert!(overlaps((2, 8), (0, 10)));
+		assert!(overlaps((3, 7), (3, 7)));
+	}
+
+	#[test]
+	fn ranges_next_to_each_other_do_not_overlap() {
+		assert!(!overlaps((0, 5), (5, 10)));
+		assert!(!overlaps((5, 10), (0, 5)));
+		assert!(!overlaps((0, 5), (7, 10)));
+	}
+
+	#[test]
+	fn insertions_clash_with_what_they_touch() {
+		assert!(overlaps((4, 4), (4, 4)));
+		assert!(!overlaps((4, 4), (5, 5)));
+		// Like the parser, at either end of a replaced range.
+		assert!(overlaps((4, 4), (4, 8)));
+		assert!(overlaps((8, 8), (4, 8)));
+		assert!(overlaps((4, 4), (2, 8)));
+		assert!(!overlaps((4, 4), (5, 8)));
+	}
+}
@@ -0,0 +1,266 @@
+//! The project configuration file, `stack-cli-util.toml`, found from the
+//! working directory or any directory above it. Meant to live next to the
+//! question bank in version control:
+//!
+//! ```toml
+//! # Actions to run when none are given on the command line.
+//! actions = ["stacklang", "files"]
+//!
+//! # Global options, by the names of the flags.
+//! [options]
+//! format = "jsonl"
+//! exclude = ["**/top/Archive/**"]
+//! only-type = ["stack"]
+//!
+//! # Options of an action, by its flag.
+//! [stackextract]
+//! parts = ["qt", "gf"]
+//!
+//! # Severities of notes, by action flag and note kind.
+//! [severity.files]
+//! unmatched_reference = "error"
+//! ```
+//!
+//! Options given on the command line replace those of the file, except
+//! `--exclude` whose patterns are added to those of the file.
+
+use crate::action::Severity;
+use crate::options::{OptionSpec, OptionValues};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+pub const CONFIG_FILE_NAME: &str = "stack-cli-util.toml";
+
+/// Options that make no sense or are too dangerous to have in a file.
+const COMMAND_LINE_ONLY: [&str; 8] = ["help", "write", "undo", "config", "no-config", "write-baseline", "watch", "interactive"];
+
+pub struct Config {
+	/// Where this was read from.
+	pub path: PathBuf,
+	/// Flags of the actions to run if none are selected on the command line.
+	pub actions: Vec<String>,
+	options: Table,
+	action_options: HashMap<String, Table>,
+	/// Severity overrides keyed by "<action flag>.<note kind>".
+	pub severity: HashMap<String, Severity>
+}
+
+impl Config {
+	/// Looks for the config file in `start` and its ancestors.
+	pub fn discover(start: &Path) -> Result<Option<Config>, String> {
+		for dir in start.ancestors() {
+			let candidate: PathBuf = dir.join(CONFIG_FILE_NAME);
+			if candidate.is_file() {
+				return Config::load(&candidate).map(Some);
+			}
+		}
+		Ok(None)
+	}
+
+	pub fn load(path: &Path) -> Result<Config, String> {
+		let content: String = std::fs::read_to_string(path)
+			.map_err(|e| format!("Could not read '{}': {}", path.display(), e))?;
+		let mut table: Table = content.parse::<Table>()
+			.map_err(|e| format!("Could not parse '{}': {}", path.display(), e))?;
+		let broken = |what: &str| format!("In '{}': {}", path.display(), what);
+
+		let actions: Vec<String> = match table.remove("actions") {
+			Some(value) => strings(&value).ok_or_else(|| broken("'actions' should be a list of action flags."))?,
+			None => Vec::new()
+		};
+		let options: Table = match table.remove("options") {
+			Some(Value::Table(options)) => options,
+			Some(_) => return Err(broken("'options' should be a table.")),
+			None => Table::new()
+		};
+		let mut severity: HashMap<String, Severity> = HashMap::new();
+		match table.remove("severity") {
+			Some(Value::Table(actions)) => {
+				for (flag, kinds) in actions {
+					let kinds: Table = match kinds {
+						Value::Table(kinds) => kinds,
+						_ => return Err(broken(&format!("'severity.{flag}' should be a table of note kinds.")))
+					};
+					for (kind, level) in kinds {
+						let level: Severity = level.as_str().and_then(Severity::from_name)
+							.ok_or_else(|| broken(&format!("'severity.{flag}.{kind}' should be one of info, warning, error.")))?;
+						severity.insert(format!("{flag}.{kind}"), level);
+					}
+				}
+			},
+			Some(_) => return Err(broken("'severity' should be a table.")),
+			None => {}
+		}
+		// The rest should be action option tables.
+		let mut action_options: HashMap<String, Table> = HashMap::new();
+		for (flag, value) in table {
+			match value {
+				Value::Table(values) => {
+					action_options.insert(flag, values);
+				},
+				_ => return Err(broken(&format!("Unknown setting '{flag}'.")))
+			}
+		}
+
+		Ok(Config {
+			path: path.to_path_buf(),
+			actions,
+			options,
+			action_options,
+			severity
+		})
+	}
+
+	/// Fills in the global options not given on the command line.
+	pub fn apply_options(&self, values: &mut OptionValues, specs: &[OptionSpec]) -> Result<(), String> {
+		for (name, value) in &self.options {
+			if COMMAND_LINE_ONLY.contains(&name.as_str()) {
+				return Err(format!("In '{}': '{name}' can only be given on the command line.", self.path.display()));
+			}
+			let spec: &OptionSpec = specs.iter().find(|s| s.name == *name)
+				.ok_or_else(|| format!("In '{}': unknown option '{name}'.", self.path.display()))?;
+			if name == "exclude" || !values.is_set(name) {
+				self.apply(values, spec, name, value)?;
+			}
+		}
+		Ok(())
+	}
+
+	/// Fills in the options of an action not given on the command line.
+	pub fn apply_action_options(&self, flag: &str, values: &mut OptionValues, specs: &[OptionSpec]) -> Result<(), String> {
+		if let Some(table) = self.action_options.get(flag) {
+			for (name, value) in table {
+				let spec: &OptionSpec = specs.iter().find(|s| s.name == *name)
+					.ok_or_else(|| format!("In '{}': unknown option '{name}' for '{flag}'.", self.path.display()))?;
+				if !values.is_set(name) {
+					self.apply(values, spec, &format!("{flag}-{name}"), value)?;
+				}
+			}
+		}
+		Ok(())
+	}
+
+	/// Action tables naming something that is not an action.
+	pub fn unknown_actions(&self, known: &[String]) -> Vec<String> {
+		let mut flags: Vec<String> = self.action_options.keys().chain(self.actions.iter()).filter(|f| !known.contains(f)).cloned().collect();
+		flags.sort();
+		flags.dedup();
+		flags
+	}
+
+	fn apply(&self, values: &mut OptionValues, spec: &OptionSpec, display_name: &str, value: &Value) -> Result<(), String> {
+		let in_file = |e: String| format!("In '{}': {}", self.path.display(), e);
+		if !spec.takes_value() {
+			match value {
+				Value::Boolean(true) => values.set(spec, Vec::new()),
+				Value::Boolean(false) => {},
+				_ => return Err(in_file(format!("--{display_name} is a flag, give it as true or false.")))
+			}
+			return Ok(());
+		}
+		let given: Vec<String> = strings(value).ok_or_else(|| in_file(format!("--{display_name} should be a string, a number or a list of those.")))?;
+		for item in given {
+			values.set(spec, spec.parse_value(&item, display_name).map_err(in_file)?);
+		}
+		Ok(())
+	}
+}
+
+/// A string, a number or a list of those, as strings.
+fn strings(value: &Value) -> Option<Vec<String>> {
+	match value {
+		Value::String(s) => Some(vec![s.clone()]),
+		Value::Integer(i) => Some(vec![i.to_string()]),
+		Value::Array(items) => items.iter().map(|i| strings(i).and_then(|s| s.into_iter().next())).collect(),
+		_ => None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Config, CONFIG_FILE_NAME};
+	use crate::action::Severity;
+	use crate::options::{OptionKind, OptionSpec, OptionValues};
+	use std::path::PathBuf;
+
+	/// A directory of its own for each test, with the given config in it.
+	fn config_dir(test: &str, content: &str) -> PathBuf {
+		let dir: PathBuf = std::env::temp_dir().join(format!("stack-cli-util-{}-{test}", std::process::id()));
+		std::fs::create_dir_all(dir.join("sub")).unwrap();
+		std::fs::write(dir.join(CONFIG_FILE_NAME), content).unwrap();
+		dir
+	}
+
+	fn load(test: &str, content: &str) -> Result<Config, String> {
+		let dir: PathBuf = config_dir(test, content);
+		let config = Config::load(&dir.join(CONFIG_FILE_NAME));
+		std::fs::remove_dir_all(dir).unwrap();
+		config
+	}
+
+	fn specs() -> Vec<OptionSpec> {
+		vec![
+			OptionSpec::new("format", OptionKind::Text, ""),
+			OptionSpec::new("exclude", OptionKind::Repeated, ""),
+			OptionSpec::new("backup", OptionKind::Flag, ""),
+			OptionSpec::new("write", OptionKind::Flag, "")
+		]
+	}
+
+	#[test]
+	fn is_found_from_directories_below() {
+		let dir: PathBuf = config_dir("discover", "actions = [\"files\"]\n");
+		let config: Option<Config> = Config::discover(&dir.join("sub")).unwrap();
+		std::fs::remove_dir_all(&dir).unwrap();
+		let config: Config = config.unwrap();
+		assert_eq!(config.path, dir.join(CONFIG_FILE_NAME));
+		assert_eq!(config.actions, vec!["files"]);
+	}
+
+	#[test]
+	fn fills_in_what_the_command_line_leaves_open() {
+		let config: Config = load("apply", "[options]\nformat = \"json\"\nexclude = [\"a/**\"]\nbackup = true\n").unwrap();
+		let specs: Vec<OptionSpec> = specs();
+		let mut values = OptionValues::new();
+		config.apply_options(&mut values, &specs).unwrap();
+		assert_eq!(values.text("format").as_deref(), Some("json"));
+		assert!(values.flag("backup"));
+
+		// The command line wins, but exclusions add up.
+		let mut values = OptionValues::new();
+		values.set(&specs[0], vec!["text".to_string()]);
+		values.set(&specs[1], vec!["b/**".to_string()]);
+		config.apply_options(&mut values, &specs).unwrap();
+		assert_eq!(values.text("format").as_deref(), Some("text"));
+		assert_eq!(values.list("exclude"), vec!["b/**", "a/**"]);
+	}
+
+	#[test]
+	fn action_options_and_severities_go_by_flag() {
+		let config: Config = load("actions", "[files]\nlimit = 3\n\n[severity.files]\nunused_file = \"error\"\n").unwrap();
+		let specs: Vec<OptionSpec> = vec![OptionSpec::new("limit", OptionKind::Number, "")];
+		let mut values = OptionValues::new();
+		config.apply_action_options("files", &mut values, &specs).unwrap();
+		assert_eq!(values.number("limit"), Some(3));
+		assert_eq!(config.severity.get("files.unused_file"), Some(&Severity::Error));
+		assert_eq!(config.unknown_actions(&["stacklang".to_string()]), vec!["files"]);
+	}
+
+	#[test]
+	fn mistakes_name_the_file_and_the_setting() {
+		let err: String = load("severity", "[severity.files]\nunused_file = \"loud\"\n").err().unwrap();
+		assert!(err.ends_with(": 'severity.files.unused_file' should be one of info, warning, error."), "{err}");
+		let err: String = load("unknown", "verbose = true\n").err().unwrap();
+		assert!(err.ends_with(": Unknown setting 'verbose'."), "{err}");
+		let err: String = load("actions-list", "actions = true\n").err().unwrap();
+		assert!(err.ends_with(": 'actions' should be a list of action flags."), "{err}");
+
+		let config: Config = load("write", "[options]\nwrite = true\n").unwrap();
+		let err: String = config.apply_options(&mut OptionValues::new(), &specs()).unwrap_err();
+		assert!(err.ends_with(": 'write' can only be given on the command line."), "{err}");
+		let config: Config = load("flag", "[options]\nbackup = \"yes\"\n").unwrap();
+		let err: String = config.apply_options(&mut OptionValues::new(), &specs()).unwrap_err();
+		assert!(err.ends_with(": --backup is a flag, give it as true or false."), "{err}");
+	}
+}
@@ -0,0 +1,144 @@
+//! Asking the local git what has changed since a revision, for checking
+//! only what a pull request touches.
+//!
+//! Both look at the working tree, so uncommitted changes count too, but
+//! files git does not track yet are not seen.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Runs git in `dir` and returns its output.
+fn git(dir: &Path, args: &[&str]) -> Result<String, String> {
+	let output = Command::new("git")
+		.arg("-C")
+		.arg(dir)
+		.args(args)
+		.output()
+		.map_err(|e| format!("Could not run git: {}", e))?;
+	if !output.status.success() {
+		return Err(format!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+	}
+	Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// The revision, unless git would take it for an option.
+fn revision(rev: &str) -> Result<&str, String> {
+	if rev.starts_with('-') {
+		return Err(format!("'{}' is not a revision.", rev));
+	}
+	Ok(rev)
+}
+
+/// The directory to run git in for a file or directory argument.
+fn directory_of(path: &Path) -> PathBuf {
+	if path.is_dir() {
+		return path.to_path_buf();
+	}
+	match path.parent() {
+		Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+		_ => PathBuf::from(".")
+	}
+}
+
+/// The files changed since `rev` in the repositories the given paths are
+/// in, as canonical paths. Deleted files are left out.
+pub fn changed_files(rev: &str, paths: &[String]) -> Result<HashSet<PathBuf>, String> {
+	let rev: &str = revision(rev)?;
+	let mut result: HashSet<PathBuf> = HashSet::new();
+	let mut repositories: HashSet<PathBuf> = HashSet::new();
+	for path in paths {
+		let top: PathBuf = PathBuf::from(git(&directory_of(Path::new(path)), &["rev-parse", "--show-toplevel"])?.trim());
+		if !repositories.insert(top.clone()) {
+			continue;
+		}
+		let names: String = git(&top, &["diff", "--name-only", "-z", "--diff-filter=d", "--no-ext-diff", rev, "--"])?;
+		for name in names.split('\0').filter(|n| !n.is_empty()) {
+			if let Ok(full) = std::fs::canonicalize(top.join(name)) {
+				result.insert(full);
+			}
+		}
+	}
+	Ok(result)
+}
+
+/// The byte ranges of `content`, the current content of `file`, that
+/// changed since `rev`. Removed lines show up as empty ranges where they
+/// used to be.
+pub fn changed_ranges(rev: &str, file: &str, content: &str) -> Result<Vec<(usize, usize)>, String> {
+	let rev: &str = revision(rev)?;
+	let path: &Path = Path::new(file);
+	let name: String = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+	let diff: String = git(&directory_of(path), &["diff", "-U0", "--no-color", "--no-ext-diff", rev, "--", &name])?;
+	Ok(hunk_ranges(&diff, content))
+}
+
+/// The byte ranges of `content` that the hunks of a `-U0` diff put there,
+/// read from the hunk headers.
+fn hunk_ranges(diff: &str, content: &str) -> Vec<(usize, usize)> {
+	// Where each line starts, and one past the end.
+	let mut line_starts: Vec<usize> = vec![0];
+	line_starts.extend(content.match_indices('\n').map(|(i, _)| i + 1));
+	let offset = |line: usize| *line_starts.get(line.saturating_sub(1)).unwrap_or(&content.len());
+
+	let mut result: Vec<(usize, usize)> = Vec::new();
+	for header in diff.lines().filter(|l| l.starts_with("@@ ")) {
+		// "@@ -12,3 +14,5 @@", the count is left out when it is one.
+		let new: &str = match header.split(' ').find(|part| part.starts_with('+')) {
+			Some(new) => &new[1..],
+			None => continue
+		};
+		let (first, count): (usize, usize) = match new.split_once(',') {
+			Some((first, count)) => (first.parse().unwrap_or(0), count.parse().unwrap_or(0)),
+			None => (new.parse().unwrap_or(0), 1)
+		};
+		if count == 0 {
+			// Lines removed after line `first`.
+			let at: usize = offset(first + 1);
+			result.push((at, at));
+		} else {
+			result.push((offset(first), offset(first + count)));
+		}
+	}
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{hunk_ranges, revision};
+
+	const CONTENT: &str = "one\ntwo\nthree\nfour\n";
+
+	#[test]
+	fn hunks_cover_their_lines() {
+		let diff: &str = "diff --git a/q.xml b/q.xml\n--- a/q.xml\n+++ b/q.xml\n@@ -2,1 +2,2 @@ <quiz>\n-2\n+two\n+three\n";
+		assert_eq!(hunk_ranges(diff, CONTENT), vec![(4, 14)]);
+	}
+
+	#[test]
+	fn a_missing_count_is_one_line() {
+		assert_eq!(hunk_ranges("@@ -1 +1 @@\n-1\n+one\n", CONTENT), vec![(0, 4)]);
+		assert_eq!(hunk_ranges("@@ -3,0 +4 @@\n+four\n", CONTENT), vec![(14, 19)]);
+	}
+
+	#[test]
+	fn removed_lines_are_empty_ranges_where_they_were() {
+		assert_eq!(hunk_ranges("@@ -3,2 +2,0 @@\n-x\n-y\n", CONTENT), vec![(8, 8)]);
+		// From the very start and past the end.
+		assert_eq!(hunk_ranges("@@ -1 +0,0 @@\n-zero\n", CONTENT), vec![(0, 0)]);
+		assert_eq!(hunk_ranges("@@ -5 +4,0 @@\n-five\n", CONTENT), vec![(19, 19)]);
+	}
+
+	#[test]
+	fn several_hunks_and_other_lines() {
+		let diff: &str = "@@ -1 +1 @@\n-1\n+one\n@@ ignored\n+@@ -9 +9 @@\n@@ -4 +4 @@\n-4\n+four\n";
+		assert_eq!(hunk_ranges(diff, CONTENT), vec![(0, 4), (14, 19)]);
+		assert!(hunk_ranges("", CONTENT).is_empty());
+	}
+
+	#[test]
+	fn revisions_are_not_options() {
+		assert_eq!(revision("main~2"), Ok("main~2"));
+		assert_eq!(revision("--output=x"), Err("'--output=x' is not a revision.".to_string()));
+	}
+}
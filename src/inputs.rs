@@ -0,0 +1,163 @@
+//! Resolves the file and directory arguments into the list of question.xml
+//! files to process.
+//!
+//! Files named directly are always processed, directories are walked
+//! recursively and only files matching the include patterns and none of
+//! the exclude patterns are picked up from them. `-` stands for the
+//! standard input.
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// What we pick from directories if nothing else has been asked for.
+pub const DEFAULT_INCLUDES: [&str; 1] = ["*.xml"];
+
+/// Things that never contain questions. The gitsync manifests are JSON
+/// so they would not match the default include, but someone including
+/// everything probably still does not want them.
+pub const DEFAULT_EXCLUDES: [&str; 3] = ["**/.git", "**/.stack-cli-util", "**/*_manifest.json"];
+
+/// The file name that means the standard input.
+pub const STDIN: &str = "-";
+
+/// Reads a file, or the standard input for `-`.
+pub fn read_input(file: &str) -> Result<String, String> {
+	if file == STDIN {
+		let mut content: String = String::new();
+		std::io::stdin().read_to_string(&mut content).map_err(|e| format!("Could not read standard input: {}", e))?;
+		return Ok(content);
+	}
+	std::fs::read_to_string(file).map_err(|e| format!("Could not read '{}': {}", file, e))
+}
+
+/// Include and exclude patterns for walking directories.
+pub struct InputFilter {
+	include: GlobSet,
+	exclude: GlobSet
+}
+
+impl InputFilter {
+	/// Builds the filter, empty `include` means the defaults. The default
+	/// excludes are always active.
+	pub fn new(include: &[String], exclude: &[String]) -> Result<InputFilter, String> {
+		let mut inc = GlobSetBuilder::new();
+		if include.is_empty() {
+			for pattern in DEFAULT_INCLUDES {
+				inc.add(Glob::new(pattern).map_err(|e| e.to_string())?);
+			}
+		} else {
+			for pattern in include {
+				inc.add(Glob::new(pattern).map_err(|e| format!("Bad include pattern '{pattern}': {e}"))?);
+			}
+		}
+		let mut exc = GlobSetBuilder::new();
+		for pattern in DEFAULT_EXCLUDES {
+			exc.add(Glob::new(pattern).map_err(|e| e.to_string())?);
+		}
+		for pattern in exclude {
+			exc.add(Glob::new(pattern).map_err(|e| format!("Bad exclude pattern '{pattern}': {e}"))?);
+		}
+		Ok(InputFilter {
+			include: inc.build().map_err(|e| e.to_string())?,
+			exclude: exc.build().map_err(|e| e.to_string())?
+		})
+	}
+
+	/// Patterns are matched against the path relative to the walked
+	/// directory and against the plain name, so that both `*.xml` and
+	/// `top/Algebra/**` work as one would expect.
+	fn matches(set: &GlobSet, relative: &Path) -> bool {
+		if set.is_match(relative) {
+			return true;
+		}
+		match relative.file_name() {
+			Some(name) => set.is_match(name),
+			None => false
+		}
+	}
+
+	/// Would walking `root` pick up `file`, both given as canonical paths.
+	pub fn accepts(&self, root: &Path, file: &Path) -> bool {
+		if root == file {
+			return true;
+		}
+		let relative: &Path = match file.strip_prefix(root) {
+			Ok(relative) => relative,
+			Err(_) => return false
+		};
+		// Any excluded directory on the way prunes it.
+		relative.ancestors().filter(|a| !a.as_os_str().is_empty()).all(|a| !self.excluded(a)) && self.included(relative)
+	}
+
+	fn excluded(&self, relative: &Path) -> bool {
+		InputFilter::matches(&self.exclude, relative)
+	}
+
+	fn included(&self, relative: &Path) -> bool {
+		InputFilter::matches(&self.include, relative)
+	}
+}
+
+/// The path of a file relative to the directory argument it was found in,
+/// or just its name if it was given itself, e.g. "Algebra/q1.xml" for
+/// "bank/Algebra/q1.xml" found in "bank".
+pub fn relative_name(file: &str, arguments: &[String]) -> PathBuf {
+	let path: &Path = Path::new(file);
+	for argument in arguments {
+		let root: &Path = Path::new(argument);
+		if let (true, Ok(relative)) = (root.is_dir(), path.strip_prefix(root)) {
+			return relative.to_path_buf();
+		}
+	}
+	path.file_name().map(PathBuf::from).unwrap_or(path.to_path_buf())
+}
+
+/// Turns the given arguments into a list of files, in a stable order.
+/// Files given directly come as they are, directories are expanded in
+/// place with their contents sorted by name.
+pub fn collect_files(arguments: &[String], filter: &InputFilter) -> Result<Vec<String>, String> {
+	let mut result: Vec<String> = Vec::new();
+	let mut seen: HashSet<String> = HashSet::new();
+
+	for argument in arguments {
+		let path = Path::new(argument);
+		if argument == STDIN {
+			if seen.insert(argument.clone()) {
+				result.push(argument.clone());
+			}
+		} else if path.is_dir() {
+			let walker = WalkDir::new(path)
+				.follow_links(true)
+				.sort_by_file_name()
+				.into_iter()
+				.filter_entry(|e| {
+					// Never prune the root itself, even if someone points us into `.git`.
+					e.depth() == 0 || !filter.excluded(e.path().strip_prefix(path).unwrap_or(e.path()))
+				});
+			for entry in walker {
+				let entry = entry.map_err(|e| format!("Issues reading directory '{argument}': {e}"))?;
+				if !entry.file_type().is_file() {
+					continue;
+				}
+				let relative = entry.path().strip_prefix(path).unwrap_or(entry.path());
+				if filter.included(relative) {
+					let name = entry.path().to_string_lossy().to_string();
+					if seen.insert(name.clone()) {
+						result.push(name);
+					}
+				}
+			}
+		} else if path.exists() {
+			if seen.insert(argument.clone()) {
+				result.push(argument.clone());
+			}
+		} else {
+			return Err(format!("No such file or directory '{argument}'."));
+		}
+	}
+
+	Ok(result)
+}
@@ -0,0 +1,172 @@
+//! Safety nets for `--write`: plain `.bak` copies and a journal of runs
+//! that `--undo` can restore from.
+//!
+//! A journal run is a directory named by its run-id, holding a copy of
+//! the original content of every written file, the changes as a unified
+//! diff and a `run.json` listing the files with the hashes of their
+//! content before and after. The listing is updated after every file so
+//! that an interrupted run can still be undone.
+
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Where journals go unless `--journal-dir` says otherwise, relative to
+/// the working directory.
+pub const DEFAULT_JOURNAL_DIR: &str = ".stack-cli-util/journal";
+
+/// Hex encoded SHA-256 of the content.
+pub fn sha256_hex(content: &[u8]) -> String {
+	Sha256::digest(content).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Writes the original content next to the file as `<file>.bak`,
+/// replacing any earlier backup.
+pub fn backup(file: &str, original: &str) -> Result<(), String> {
+	let target: String = format!("{file}.bak");
+	std::fs::write(&target, original).map_err(|e| format!("Could not write backup '{}': {}", target, e))
+}
+
+/// The journal of a single run.
+pub struct Journal {
+	dir: PathBuf,
+	run_id: String,
+	entries: Vec<Value>
+}
+
+impl Journal {
+	/// Creates the directory for a new run under `base`.
+	pub fn start(base: &Path) -> Result<Journal, String> {
+		let run_id: String = new_run_id();
+		let dir: PathBuf = base.join(&run_id);
+		std::fs::create_dir_all(&dir).map_err(|e| format!("Could not create journal '{}': {}", dir.display(), e))?;
+		let journal = Journal {
+			dir,
+			run_id,
+			entries: Vec::new()
+		};
+		journal.save()?;
+		Ok(journal)
+	}
+
+	pub fn run_id(&self) -> &str {
+		&self.run_id
+	}
+
+	/// Records a file about to be written. Call before writing so that the
+	/// original is safe even if the writing fails halfway.
+	pub fn record(&mut self, file: &str, original: &str, modified: &str, diff: &str) -> Result<(), String> {
+		let n: usize = self.entries.len() + 1;
+		// Not `.xml` so that walking the directory does not pick these up.
+		let original_copy: String = format!("{n:04}.orig");
+		let diff_copy: String = format!("{n:04}.diff");
+		std::fs::write(self.dir.join(&original_copy), original)
+			.and_then(|_| std::fs::write(self.dir.join(&diff_copy), diff))
+			.map_err(|e| format!("Could not write to journal '{}': {}", self.dir.display(), e))?;
+		// Absolute so that undoing works from elsewhere.
+		let path: PathBuf = std::fs::canonicalize(file).unwrap_or_else(|_| PathBuf::from(file));
+		self.entries.push(json!({
+			"file": path.to_string_lossy(),
+			"original": original_copy,
+			"original_sha256": sha256_hex(original.as_bytes()),
+			"modified_sha256": sha256_hex(modified.as_bytes()),
+			"changes": diff_copy
+		}));
+		self.save()
+	}
+
+	fn save(&self) -> Result<(), String> {
+		let listing = json!({
+			"run_id": self.run_id,
+			"files": self.entries
+		});
+		std::fs::write(self.dir.join("run.json"), serde_json::to_string_pretty(&listing).unwrap_or_default())
+			.map_err(|e| format!("Could not write to journal '{}': {}", self.dir.display(), e))
+	}
+}
+
+/// What happened to a single file when undoing.
+pub struct UndoResult {
+	pub file: String,
+	/// False if the file was left as is, e.g. because it has been changed
+	/// after the run.
+	pub restored: bool,
+	pub message: String
+}
+
+/// Restores the files written in the given run. Files that have changed
+/// since are left alone.
+pub fn undo(base: &Path, run_id: &str) -> Result<Vec<UndoResult>, String> {
+	let dir: PathBuf = base.join(run_id);
+	let listing: String = std::fs::read_to_string(dir.join("run.json"))
+		.map_err(|e| format!("No journal for run '{}' in '{}': {}", run_id, base.display(), e))?;
+	let listing: Value = serde_json::from_str(&listing)
+		.map_err(|e| format!("Broken journal for run '{}': {}", run_id, e))?;
+	let entries: Vec<Value> = listing["files"].as_array().cloned().unwrap_or_default();
+
+	let mut results: Vec<UndoResult> = Vec::new();
+	// Backwards, in case the same file was written more than once.
+	for entry in entries.iter().rev() {
+		let (Some(file), Some(copy), Some(original_hash), Some(modified_hash)) = (entry["file"].as_str(), entry["original"].as_str(), entry["original_sha256"].as_str(), entry["modified_sha256"].as_str()) else {
+			return Err(format!("Broken journal for run '{}', an entry is missing fields.", run_id));
+		};
+		let result = |restored: bool, message: String| UndoResult {
+			file: file.to_string(),
+			restored,
+			message
+		};
+		let current: String = match std::fs::read_to_string(file) {
+			Ok(current) => current,
+			Err(e) => {
+				results.push(result(false, format!("Could not read, not restoring: {}", e)));
+				continue;
+			}
+		};
+		let current_hash: String = sha256_hex(current.as_bytes());
+		if current_hash == original_hash {
+			results.push(result(true, "Already in its original state.".to_string()));
+			continue;
+		}
+		if current_hash != modified_hash {
+			results.push(result(false, "Modified after the run, not restoring.".to_string()));
+			continue;
+		}
+		let original: String = match std::fs::read_to_string(dir.join(copy)) {
+			Ok(original) if sha256_hex(original.as_bytes()) == original_hash => original,
+			_ => {
+				results.push(result(false, "The journal copy is missing or does not match its hash, not restoring.".to_string()));
+				continue;
+			}
+		};
+		match crate::save::write_atomic(file, &original) {
+			Ok(_) => results.push(result(true, "Restored.".to_string())),
+			Err(e) => results.push(result(false, e))
+		}
+	}
+	results.reverse();
+	Ok(results)
+}
+
+/// A sortable and readable id, e.g. "20261018-142501-4242", the process
+/// id keeps parallel runs apart.
+fn new_run_id() -> String {
+	let seconds: u64 = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+	let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+	let time: u64 = seconds % 86400;
+	format!("{:04}{:02}{:02}-{:02}{:02}{:02}-{}", year, month, day, time / 3600, time % 3600 / 60, time % 60, std::process::id())
+}
+
+/// Days since 1970-01-01 to a (year, month, day) in UTC.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+	let z: i64 = days + 719468;
+	let era: i64 = z.div_euclid(146097);
+	let doe: i64 = z - era * 146097;
+	let yoe: i64 = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+	let doy: i64 = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp: i64 = (5 * doy + 2) / 153;
+	let day: u32 = (doy - (153 * mp + 2) / 5 + 1) as u32;
+	let month: u32 = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+	let year: i64 = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+	(year, month, day)
+}
@@ -0,0 +1,44 @@
+//! Actions for processing Moodle question-xml files, in particular STACK
+//! questions in them, usable both through the `stack-cli-util` binary and
+//! as a library.
+//!
+//! A minimal embedding configures the wanted actions and lets a `Runner`
+//! process files or already loaded `QParser`s:
+//!
+//! ```no_run
+//! use stack_cli_util::{Context, Registry, Runner};
+//!
+//! let actions = Registry::with_builtin_actions().unwrap();
+//! let context = Context { write: false, diff: true, file: None, roots: Vec::new() };
+//! let mut runner = Runner::new(actions, vec!["stacklang".to_string()], context);
+//! let result = runner.process_file("questions.xml").unwrap();
+//! if let Some(modified) = result.modified {
+//!     println!("{}", modified);
+//! }
+//! ```
+
+pub mod action;
+pub mod actions;
+pub mod args;
+pub mod baseline;
+pub mod changes;
+pub mod config;
+pub mod git;
+pub mod inputs;
+pub mod journal;
+pub mod options;
+pub mod panics;
+pub mod pluginfile;
+pub mod output;
+pub mod registry;
+pub mod review;
+pub mod runner;
+pub mod save;
+pub mod select;
+pub mod status;
+pub mod watch;
+
+pub use action::{Action, ActionError, Context, Counters, Note, Severity};
+pub use registry::Registry;
+pub use runner::{ActionNote, FileResult, QuestionResult, Runner};
+pub use status::Status;
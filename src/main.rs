@@ -1,161 +1,472 @@
-mod action;
-mod actions;
+use stack_cli_util::{Context, FileResult, Registry, Runner, Severity, Status};
+use stack_cli_util::args::{Arguments, global_options};
+use stack_cli_util::baseline::{Baseline, DEFAULT_BASELINE_FILE};
+use stack_cli_util::config::Config;
+use stack_cli_util::git;
+use stack_cli_util::inputs::{self, InputFilter};
+use stack_cli_util::journal::{self, Journal};
+use stack_cli_util::options::{OptionSpec, options_table};
+use stack_cli_util::output::{Output, QuestionInfo, Skipped};
+use stack_cli_util::review::Interactive;
+use stack_cli_util::save;
+use stack_cli_util::select::Selection;
+use stack_cli_util::watch;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use position_preserving_moodle_question_xml_edit::{QParser, Question};
-use crate::actions::attachments::FileAttachmentChecker;
-use crate::actions::stack_lang::LangSyntaxConverter;
-use crate::actions::stack_extractor::StackExtractor;
-use crate::action::Action;
 
+fn main() {
+    let actions: Registry = match Registry::with_builtin_actions() {
+        Ok(registry) => registry,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(Status::Error.code());
+        }
+    };
 
-// All known action types.
-enum Actions {
-    A0(FileAttachmentChecker),
-    A1(LangSyntaxConverter),
-    A2(StackExtractor)
-}
+    let argv: Vec<String> = std::env::args().skip(1).collect();
+    let declarations: Vec<(String, Vec<OptionSpec>)> = actions.iter().map(|a| (a.flag(), a.options())).collect();
+    let (args, config): (Arguments, Option<Config>) = match parse_arguments(&argv, &declarations) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(Status::Error.code());
+        }
+    };
 
-// I have not yet quite grokked the way to work with traits and vectors.
-// So this is the mess to deal with them.
-impl Action for Actions {
-    fn process(&mut self, question: &Question, parser: &mut QParser, flags: Vec<String>) -> (bool, Vec<String>) {
-        match self {
-            Actions::A0(a) => {a.process(question, parser, flags)}
-            Actions::A1(a) => {a.process(question, parser, flags)}
-            Actions::A2(a) => {a.process(question, parser, flags)}
+    if args.help() {
+        // Either the asked one, the selected ones or all.
+        for action in actions.iter() {
+            let flag: String = action.flag();
+            let wanted: bool = match &args.help_topic {
+                Some(topic) => *topic == flag,
+                None => args.actions.is_empty() || args.selected(&flag)
+            };
+            if wanted {
+                println!(" --{} {}", flag, action.name());
+                println!("{}", action.description());
+                let specs: Vec<OptionSpec> = action.options();
+                if !specs.is_empty() {
+                    println!("\nOptions:");
+                    print!("{}", options_table(&specs, &format!("{flag}-")));
+                }
+                println!("\n");
+            }
         }
+        return;
     }
 
-    fn name(&self) -> String {
-        match self {
-            Actions::A0(a) => {a.name()}
-            Actions::A1(a) => {a.name()}
-            Actions::A2(a) => {a.name()}
+    if let Some(run_id) = args.undo() {
+        let results = match journal::undo(Path::new(&args.journal_dir()), &run_id) {
+            Ok(results) => results,
+            Err(e) => {
+                println!("{}", e);
+                std::process::exit(Status::Error.code());
+            }
+        };
+        let mut status: Status = Status::Clean;
+        for result in results {
+            println!("{}: {}", result.file, result.message);
+            if !result.restored {
+                status = Status::Error;
+            }
         }
+        std::process::exit(status.code());
     }
 
-    fn flag(&self) -> String {
-        match self {
-            Actions::A0(a) => {a.flag()}
-            Actions::A1(a) => {a.flag()}
-            Actions::A2(a) => {a.flag()}
+    if argv.is_empty() || args.paths.is_empty() || args.actions.is_empty() {
+        println!("To use this tool you need to provide, both filename(s)
+and some flags to define the actions to take. Directories are walked
+recursively.");
+        println!("\nGeneral options:");
+        print!("{}", options_table(&global_options(), ""));
+        println!("\nCurrently known actions:");
+        for action in actions.iter() {
+            println!(" --{} {}", action.flag(), action.name());
         }
+        
+        return;
     }
 
-    fn description(&self) -> String {
-        match self {
-            Actions::A0(a) => {a.description()}
-            Actions::A1(a) => {a.description()}
-            Actions::A2(a) => {a.description()}
-        }
+    if args.interactive() && (args.stdout() || args.watch() || args.paths.iter().any(|p| p == inputs::STDIN)) {
+        println!("--interactive reads the answers from stdin, it does not go with --stdout, --watch or - as input.");
+        std::process::exit(Status::Error.code());
+    }
+    if args.interactive() && args.write() {
+        println!("--interactive writes what is accepted, it does not go with --write.");
+        std::process::exit(Status::Error.code());
+    }
+    if args.watch() && (args.write() || args.stdout() || args.write_baseline()) {
+        println!("--watch only checks, it does not go with --write, --stdout or --write-baseline.");
+        std::process::exit(Status::Error.code());
     }
 
-    fn supports(&self, qtype: String) -> bool {
-        match self {
-            Actions::A0(a) => {a.supports(qtype)}
-            Actions::A1(a) => {a.supports(qtype)}
-            Actions::A2(a) => {a.supports(qtype)}
+    let selection: Selection = match Selection::from_options(&args.global) {
+        Ok(selection) => selection,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(Status::Error.code());
         }
+    };
+
+    // Only --write lets actions do things, printing the result and asking
+    // about the changes just show what they would look like. In the
+    // interactive mode whatever is accepted gets written after asking.
+    let context: Context = Context {
+        write: args.write(),
+        diff: args.diff() || args.stdout() || args.interactive(),
+        file: None,
+        roots: args.paths.clone()
+    };
+    let save_changes: bool = args.write() || args.interactive();
+    let fail_on: Status = args.fail_on();
+    let mut output: Output = Output::new(args.format());
+    if args.stdout() {
+        output = output.to_stderr();
     }
+    let mut progress: Progress = Progress {
+        output,
+        status: Status::Clean,
+        skipped: Vec::new()
+    };
+    let mut journal: Option<Journal> = None;
 
-    fn report(&self) -> Option<String> {
-        match self {
-            Actions::A0(a) => {a.report()}
-            Actions::A1(a) => {a.report()}
-            Actions::A2(a) => {a.report()}
+    // Known findings to leave out, or to record.
+    let mut baseline: Option<Baseline> = match (args.write_baseline(), args.baseline()) {
+        (true, path) => Some(Baseline::new(Path::new(&path.unwrap_or_else(|| DEFAULT_BASELINE_FILE.to_string())))),
+        (false, Some(path)) => match Baseline::load(Path::new(&path)) {
+            Ok(baseline) => Some(baseline),
+            Err(e) => {
+                println!("{}", e);
+                std::process::exit(Status::Error.code());
+            }
+        },
+        (false, None) => None
+    };
+    let mut known: usize = 0;
+
+    // Resolve the directories to files.
+    let include: Vec<String> = args.global.list("include");
+    let exclude: Vec<String> = args.global.list("exclude");
+    let filter: InputFilter = match InputFilter::new(&include, &exclude) {
+        Ok(filter) => filter,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(Status::Error.code());
         }
-    }
-}
+    };
+    let files: Vec<String> = match inputs::collect_files(&args.paths, &filter) {
+        Ok(files) => files,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(Status::Error.code());
+        }
+    };
 
+    // Of those, only the ones git says have changed.
+    let files: Vec<String> = match args.changed_since() {
+        Some(rev) => match git::changed_files(&rev, &args.paths) {
+            Ok(changed) => files.into_iter().filter(|f| std::fs::canonicalize(f).is_ok_and(|f| changed.contains(&f))).collect(),
+            Err(e) => {
+                println!("{}", e);
+                std::process::exit(Status::Error.code());
+            }
+        },
+        None => {
+            if args.changed_questions() {
+                println!("--changed-questions needs --changed-since.");
+                std::process::exit(Status::Error.code());
+            }
+            files
+        }
+    };
 
-fn main() {
-    // Simple arguments.
-    let args: Vec<String> = std::env::args().collect();
-    let files: Vec<&String> = args[1..].iter().filter(|a| !a.starts_with("--")).collect();
-    let flags: Vec<String> = args[1..].iter().filter(|a| a.starts_with("--")).map(|a| a[2..].to_string()).collect();
-    
-    // Init all known action types here.
-    let mut actions: Vec<Actions> = vec![
-        Actions::A0(FileAttachmentChecker::new()),
-        Actions::A1(LangSyntaxConverter::new()),
-        Actions::A2(StackExtractor::new())
-    ];
-    
-
-    if flags.contains(&"help".to_string()) {
-        for action in &mut actions {
-            println!(" --{} {}", action.flag(), action.name());
-            println!("{}", action.description());
-            println!("\n");
+    // In a pipe the input is read here so that it can be passed on as it
+    // was should processing fail.
+    let input: Option<String> = if args.stdout() {
+        if args.write() || files.len() != 1 {
+            println!("--stdout takes a single input file, or - for stdin, and no --write.");
+            std::process::exit(Status::Error.code());
         }
-        return;
-    }
+        match inputs::read_input(&files[0]) {
+            Ok(content) => Some(content),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(Status::Error.code());
+            }
+        }
+    } else {
+        if args.write() && files.iter().any(|f| f == inputs::STDIN) {
+            println!("Cannot --write to the standard input, use --stdout.");
+            std::process::exit(Status::Error.code());
+        }
+        None
+    };
 
-    if args.len() == 1 || files.is_empty() || flags.is_empty() {
-        println!("To use this tool you need to provide, both filename(s)
-and some flags to define the actions to take.");
-        println!("\nCurrently known actions:");
-        println!(" --help Describes actions in some more detail");
-        println!(" --write The general write flag to execute things not just report");
-        
-        for action in &actions {
-            println!(" --{} {}", action.flag(), action.name());
+    // Then process the files, each worker with its own configured actions.
+    let severities: HashMap<String, Severity> = config.map(|c| c.severity).unwrap_or_default();
+    let mut runners: Vec<Runner> = Vec::new();
+    // The questions need to be asked one at a time.
+    let jobs: usize = if args.interactive() { 1 } else { args.jobs() };
+    let quit: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+    for _ in 0..jobs {
+        match configured_actions(&args) {
+            Ok(actions) => {
+                let mut runner: Runner = Runner::new(actions, args.actions.clone(), context.clone())
+                    .with_selection(selection.clone())
+                    .with_severities(severities.clone());
+                if let (true, Some(rev)) = (args.changed_questions(), args.changed_since()) {
+                    runner = runner.with_changed_since(&rev);
+                }
+                if args.interactive() {
+                    runner = runner.with_review(Box::new(Interactive::new(quit.clone())));
+                }
+                runners.push(runner);
+            },
+            Err(e) => {
+                println!("{}", e);
+                std::process::exit(Status::Error.code());
+            }
         }
-        
-        return;
     }
-
-    // Then process the files.
-    for file_name in files {
-        println!("Checking {}:", file_name.clone());
-        let mut parser = QParser::load_xml_file(file_name.clone()).expect("Something bad with the file or file-name.");
-        let mut any_changes: bool = false;
-        let mut questions: Vec<Question> = parser.find_questions();
-        for qi in 0..questions.len() {
-            println!(" {:>3}/{} '{}':", qi + 1, questions.len(), questions[qi].name.unwrap_cdata());
-
-            for action in &mut actions {
-                // Because that lack of vector of these.
-                if flags.contains(&action.flag()) && action.supports(questions[qi].qtype.clone()) {
-                    let (changes, notes) = action.process(&questions[qi], &mut parser, flags.clone());
-                    if changes {
-                        any_changes = true;
-                        // Something changed, the questions list is not going to be correct.
-                        questions = parser.find_questions();
-                    }
-                    for note in notes {
-                        println!("  {}", note);
-                    }
+    let mut runner: Runner = runners.remove(0);
+    let mut write_failed: bool = false;
+    let mut handle = |progress: &mut Progress, file_name: &str, result: Result<FileResult, String>| {
+        let mut result = match result {
+            Ok(result) => result,
+            Err(e) => {
+                if let Some(content) = &input {
+                    print_content(content);
                 }
+                progress.output.error(file_name, &e);
+                progress.status = progress.status.with(Status::Error);
+                progress.skipped.push(Skipped {
+                    file: file_name.to_string(),
+                    question: None,
+                    action: None,
+                    message: e
+                });
+                return true;
+            }
+        };
+        if let Some(baseline) = &mut baseline {
+            // What is being recorded counts as known from now on.
+            if args.write_baseline() {
+                baseline.record(&result);
+            }
+            known += baseline.filter(&mut result);
+        }
+        progress.status = progress.status.with(result.status());
+
+        progress.output.file(file_name, result.question_count);
+        for question in &result.questions {
+            let info = QuestionInfo {
+                index: question.index,
+                count: result.question_count,
+                name: &question.name,
+                qtype: &question.qtype
+            };
+            progress.output.question(&info);
+            for note in &question.notes {
+                progress.output.note(file_name, &info, &note.action, &note.note);
+            }
+            if let Some((action, message)) = &question.failure {
+                progress.skipped.push(Skipped {
+                    file: file_name.to_string(),
+                    question: Some(question.name.clone()),
+                    action: Some(action.clone()),
+                    message: message.clone()
+                });
+            }
+        }
+
+        for conflict in &result.conflicts {
+            let question_name: String = result.questions.iter().find(|q| q.index == conflict.question).map(|q| q.name.clone()).unwrap_or_default();
+            progress.output.conflict(file_name, &question_name, conflict);
+        }
+        if !result.conflicts.is_empty() {
+            progress.skipped.push(Skipped {
+                file: file_name.to_string(),
+                question: None,
+                action: None,
+                message: format!("{} conflicting changes, the file was not changed.", result.conflicts.len())
+            });
+        }
+
+        if args.diff() {
+            if let Some(diff) = result.diff() {
+                progress.output.diff(file_name, &diff);
             }
         }
-        if any_changes && flags.contains(&"write".to_string()) {
-            match parser.save_to_file(file_name.clone()) {
-                Ok(_) => {
-                    // Nothing.
-                },
-                Err(e) => {
-                    println!("Issues writing changes to '{}', stopping.", file_name.clone());
-                    println!("{:?}", e);
-                    return;
+        if let Some(content) = &input {
+            // Only pass on changes that check out.
+            let mut passed: &str = content;
+            if let Some(modified) = &result.modified {
+                match save::verify(&result.original, modified) {
+                    Ok(()) => passed = modified,
+                    Err(e) => {
+                        progress.output.error(file_name, &format!("Passing '{}' on unchanged: {}", file_name, e));
+                        progress.status = progress.status.with(Status::Error);
+                    }
                 }
             }
+            print_content(passed);
+        } else if let (true, Some(modified)) = (save_changes, &result.modified) {
+            // Broken changes skip the file, nothing has been touched yet.
+            if let Err(e) = save::verify(&result.original, modified) {
+                let message: String = format!("Not writing '{}': {}", file_name, e);
+                progress.output.error(file_name, &message);
+                progress.status = progress.status.with(Status::Error);
+                progress.skipped.push(Skipped {
+                    file: file_name.to_string(),
+                    question: None,
+                    action: None,
+                    message
+                });
+                return true;
+            }
+            if let Err(e) = save(&result, &args, &mut journal) {
+                progress.output.error(file_name, &format!("Issues writing changes to '{}', stopping. {}", file_name, e));
+                write_failed = true;
+                return false;
+            }
+        }
+        // Told to quit, what was accepted so far is written.
+        !quit.load(Ordering::Relaxed)
+    };
+    match &input {
+        Some(content) => {
+            let result = runner.process_content(&files[0], content.clone());
+            handle(&mut progress, &files[0], result);
+        },
+        None => runner.process_files(&files, runners, &mut |file_name: &str, result| handle(&mut progress, file_name, result))
+    }
+
+    if args.watch() {
+        // Each pass is reported on its own, so that scripts get complete
+        // records without waiting for the end.
+        progress.end_pass(files.len());
+        let watched = watch::watch(&args.paths, &filter, |changed| {
+            progress.status = Status::Clean;
+            progress.skipped.clear();
+            runner.process_files(changed, Vec::new(), &mut |file_name: &str, result| handle(&mut progress, file_name, result));
+            progress.end_pass(changed.len());
+        });
+        if let Err(e) = watched {
+            progress.output.error(&args.paths.join(" "), &e);
+            progress.output.finish();
         }
+        std::process::exit(Status::Error.code());
+    }
+
+    let Progress { mut output, mut status, skipped } = progress;
+    if write_failed {
+        output.finish();
+        print_journal(&journal);
+        std::process::exit(Status::Error.exit_code(fail_on));
     }
 
     // Provide end reports.
-    for action in &actions {
-        if flags.contains(&action.flag()) {
-            let report: Option<String> = action.report();
-            match report {
-                Some(r) => {
-                    println!("\nEnd report from '{}'", action.name());
-                    println!("{}", r);
-                },
-                None => {
-                    // Nothing to report.
-                }
+    output.summary(files.len(), &skipped);
+    if let Some(baseline) = &baseline {
+        let path: String = baseline.path().display().to_string();
+        if args.write_baseline() {
+            if let Err(e) = baseline.save() {
+                output.error(&path, &e);
+                status = status.with(Status::Error);
+            } else {
+                output.baseline_written(&path);
             }
+        } else {
+            output.baseline(&path, known, baseline.stale());
+        }
+    }
+    for action in runner.selected_actions() {
+        output.report(&action.flag(), &action.name(), action.report(), &action.counters());
+    }
+    output.finish();
+    print_journal(&journal);
+    std::process::exit(status.exit_code(fail_on));
+}
+
+/// The output and what it has been told so far, over the whole run or a
+/// single pass when watching.
+struct Progress {
+    output: Output,
+    status: Status,
+    skipped: Vec<Skipped>
+}
+
+impl Progress {
+    /// Sums up a pass of watching and says that more are to come.
+    fn end_pass(&mut self, file_count: usize) {
+        self.output.summary(file_count, &self.skipped);
+        self.output.finish();
+        self.output.watching();
+    }
+}
+
+/// Parses the arguments, with the configuration file unless told to skip
+/// it. The first pass is only needed to see if we are told so.
+fn parse_arguments(argv: &[String], declarations: &[(String, Vec<OptionSpec>)]) -> Result<(Arguments, Option<Config>), String> {
+    let plain: Arguments = Arguments::parse(argv, declarations, None)?;
+    let config: Option<Config> = if plain.global.flag("no-config") {
+        None
+    } else if let Some(path) = plain.global.text("config") {
+        Some(Config::load(Path::new(&path))?)
+    } else {
+        let here = std::env::current_dir().map_err(|e| e.to_string())?;
+        Config::discover(&here)?
+    };
+    match config {
+        Some(config) => Ok((Arguments::parse(argv, declarations, Some(&config))?, Some(config))),
+        None => Ok((plain, None))
+    }
+}
+
+/// A fresh set of actions with the selected ones configured.
+fn configured_actions(args: &Arguments) -> Result<Registry, String> {
+    let mut actions: Registry = Registry::with_builtin_actions()?;
+    for action in actions.iter_mut() {
+        if args.selected(&action.flag()) {
+            action.configure(&args.action_options(&action.flag())).map_err(|e| format!("--{}: {}", action.flag(), e))?;
+        }
+    }
+    Ok(actions)
+}
+
+/// Writes the resulting XML to stdout as it is.
+fn print_content(content: &str) {
+    let mut stdout = std::io::stdout();
+    if let Err(e) = stdout.write_all(content.as_bytes()).and_then(|_| stdout.flush()) {
+        eprintln!("Could not write to stdout: {}", e);
+    }
+}
+
+/// Tells how to undo the run, if it was journaled.
+fn print_journal(journal: &Option<Journal>) {
+    if let Some(journal) = journal {
+        eprintln!("Journal run '{}' written, undo with --undo={}", journal.run_id(), journal.run_id());
+    }
+}
+
+/// Writes a changed file, taking the backup and journaling it first if
+/// those were asked for.
+fn save(result: &FileResult, args: &Arguments, journal: &mut Option<Journal>) -> Result<(), String> {
+    let modified: &str = result.modified.as_deref().unwrap_or_default();
+    if args.backup() {
+        journal::backup(&result.file, &result.original)?;
+    }
+    if args.journal() {
+        if journal.is_none() {
+            *journal = Some(Journal::start(Path::new(&args.journal_dir()))?);
+        }
+        if let Some(journal) = journal {
+            journal.record(&result.file, &result.original, modified, &result.diff().unwrap_or_default())?;
         }
     }
+    save::write_atomic(&result.file, modified)
 }
@@ -0,0 +1,180 @@
+//! Declarations of options, both the global ones and those of actions,
+//! and the values given to them.
+//!
+//! Actions declare their options without the prefix, on the command line
+//! they are namespaced by the flag of the action, e.g. the `parts` option
+//! of `--stackextract` is given as `--stackextract-parts=qt,gf`.
+
+use std::collections::HashMap;
+
+/// The kind of value an option takes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OptionKind {
+	/// Present or not, e.g. `--write`.
+	Flag,
+	/// One of the listed values.
+	Choice(Vec<String>),
+	/// Comma separated values from the listed ones, repeats accumulate.
+	List(Vec<String>),
+	/// Free text, the last one given counts.
+	Text,
+	/// A non-negative integer.
+	Number,
+	/// Free text that may be given many times, e.g. glob patterns.
+	Repeated
+}
+
+/// Declaration of a single option.
+#[derive(Debug, Clone)]
+pub struct OptionSpec {
+	pub name: String,
+	pub kind: OptionKind,
+	pub default: Option<String>,
+	pub description: String
+}
+
+impl OptionSpec {
+	pub fn new(name: &str, kind: OptionKind, description: &str) -> OptionSpec {
+		OptionSpec {
+			name: name.to_string(),
+			kind,
+			default: None,
+			description: description.to_string()
+		}
+	}
+
+	pub fn default(mut self, value: &str) -> OptionSpec {
+		self.default = Some(value.to_string());
+		self
+	}
+
+	/// Does this take a value.
+	pub fn takes_value(&self) -> bool {
+		self.kind != OptionKind::Flag
+	}
+
+	/// Checks a given value and splits it to parts if need be.
+	pub fn parse_value(&self, given: &str, display_name: &str) -> Result<Vec<String>, String> {
+		match &self.kind {
+			OptionKind::Flag => Ok(Vec::new()),
+			OptionKind::Text | OptionKind::Repeated => Ok(vec![given.to_string()]),
+			OptionKind::Number => match given.parse::<usize>() {
+				Ok(_) => Ok(vec![given.to_string()]),
+				Err(_) => Err(format!("--{display_name} expects a number, got '{given}'."))
+			},
+			OptionKind::Choice(allowed) => {
+				if allowed.iter().any(|a| a == given) {
+					Ok(vec![given.to_string()])
+				} else {
+					Err(format!("--{display_name} expects one of {}, got '{given}'.", allowed.join(", ")))
+				}
+			},
+			OptionKind::List(allowed) => {
+				let mut result: Vec<String> = Vec::new();
+				for bit in given.split(',') {
+					let bit = bit.trim();
+					if bit.is_empty() {
+						continue;
+					}
+					if !allowed.iter().any(|a| a == bit) {
+						return Err(format!("--{display_name} expects values from {}, got '{bit}'.", allowed.join(", ")));
+					}
+					result.push(bit.to_string());
+				}
+				Ok(result)
+			}
+		}
+	}
+
+	/// The value placeholder shown in the help.
+	fn value_hint(&self) -> String {
+		match &self.kind {
+			OptionKind::Flag => String::new(),
+			OptionKind::Choice(allowed) => format!("={}", allowed.join("|")),
+			OptionKind::List(allowed) => format!("={}[,...]", allowed.join("|")),
+			OptionKind::Text => "=<text>".to_string(),
+			OptionKind::Number => "=<n>".to_string(),
+			OptionKind::Repeated => "=<text> (repeatable)".to_string()
+		}
+	}
+}
+
+/// Renders a table of options for the help output. `prefix` is the
+/// namespace of action options, e.g. "stackextract-".
+pub fn options_table(specs: &[OptionSpec], prefix: &str) -> String {
+	let mut result: String = String::new();
+	let heads: Vec<String> = specs.iter().map(|s| format!(" --{}{}{}", prefix, s.name, s.value_hint())).collect();
+	let width: usize = heads.iter().map(|h| h.len()).max().unwrap_or(0);
+	for (head, spec) in heads.iter().zip(specs) {
+		let mut description: String = spec.description.clone();
+		if let Some(default) = &spec.default {
+			description.push_str(&format!(" [default: {default}]"));
+		}
+		let mut lines = description.lines();
+		result.push_str(&format!("{:width$}  {}\n", head, lines.next().unwrap_or("")));
+		for line in lines {
+			result.push_str(&format!("{:width$}  {}\n", "", line));
+		}
+	}
+	result
+}
+
+/// The values given to a set of options, with the defaults filled in.
+#[derive(Debug, Clone, Default)]
+pub struct OptionValues {
+	values: HashMap<String, Vec<String>>
+}
+
+impl OptionValues {
+	pub fn new() -> OptionValues {
+		OptionValues {
+			values: HashMap::new()
+		}
+	}
+
+	/// Stores a parsed value, accumulating for those kinds that do so.
+	pub fn set(&mut self, spec: &OptionSpec, parsed: Vec<String>) {
+		match spec.kind {
+			OptionKind::List(_) | OptionKind::Repeated => {
+				self.values.entry(spec.name.clone()).or_default().extend(parsed);
+			},
+			_ => {
+				self.values.insert(spec.name.clone(), parsed);
+			}
+		}
+	}
+
+	/// Fills in the defaults for everything not given.
+	pub fn fill_defaults(&mut self, specs: &[OptionSpec]) {
+		for spec in specs {
+			if !self.values.contains_key(&spec.name) {
+				if let Some(default) = &spec.default {
+					// Defaults are declared by us, so they are valid.
+					let parsed = spec.parse_value(default, &spec.name).unwrap_or_default();
+					self.values.insert(spec.name.clone(), parsed);
+				}
+			}
+		}
+	}
+
+	/// Was this given, as opposed to defaulted or absent.
+	pub fn is_set(&self, name: &str) -> bool {
+		self.values.contains_key(name)
+	}
+
+	pub fn flag(&self, name: &str) -> bool {
+		self.values.contains_key(name)
+	}
+
+	pub fn text(&self, name: &str) -> Option<String> {
+		self.values.get(name).and_then(|v| v.last().cloned())
+	}
+
+	pub fn number(&self, name: &str) -> Option<usize> {
+		self.text(name).and_then(|v| v.parse::<usize>().ok())
+	}
+
+	pub fn list(&self, name: &str) -> Vec<String> {
+		self.values.get(name).cloned().unwrap_or_default()
+	}
+}
@@ -0,0 +1,288 @@
+//! Printing of notes and reports, either as text for humans or as JSON
+//! for scripts.
+//!
+//! In JSON Lines mode every record is printed as soon as it is known, in
+//! JSON mode the records are collected and printed as a single array at
+//! the end.
+
+use crate::action::{Counters, Note, Severity};
+use crate::changes::{Conflict, ConflictSide};
+use serde_json::{json, Map, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+	Text,
+	Json,
+	JsonLines
+}
+
+impl Format {
+	pub fn from_name(name: &str) -> Option<Format> {
+		match name {
+			"text" => Some(Format::Text),
+			"json" => Some(Format::Json),
+			"jsonl" => Some(Format::JsonLines),
+			_ => None
+		}
+	}
+}
+
+/// The question a note is about.
+pub struct QuestionInfo<'a> {
+	/// 1-based, as shown in the text output.
+	pub index: usize,
+	pub count: usize,
+	pub name: &'a str,
+	pub qtype: &'a str
+}
+
+/// A question or a whole file that could not be processed.
+pub struct Skipped {
+	pub file: String,
+	/// Name of the question, None if the whole file was skipped.
+	pub question: Option<String>,
+	pub action: Option<String>,
+	pub message: String
+}
+
+pub struct Output {
+	format: Format,
+	records: Vec<Value>,
+	/// Print to stderr, stdout being taken by the XML.
+	to_stderr: bool
+}
+
+impl Output {
+	pub fn new(format: Format) -> Output {
+		Output {
+			format,
+			records: Vec::new(),
+			to_stderr: false
+		}
+	}
+
+	/// Prints everything to stderr instead of stdout.
+	pub fn to_stderr(mut self) -> Output {
+		self.to_stderr = true;
+		self
+	}
+
+	fn text(&self, text: &str) {
+		if self.to_stderr {
+			eprint!("{}", text);
+		} else {
+			print!("{}", text);
+		}
+	}
+
+	fn line(&self, text: &str) {
+		self.text(&format!("{}\n", text));
+	}
+
+	fn record(&mut self, record: Value) {
+		match self.format {
+			Format::Text => {},
+			Format::Json => self.records.push(record),
+			Format::JsonLines => self.line(&record.to_string())
+		}
+	}
+
+	/// Start of a file.
+	pub fn file(&mut self, file: &str, question_count: usize) {
+		if self.format == Format::Text {
+			self.line(&format!("Checking {}:", file));
+		} else {
+			self.record(json!({
+				"type": "file",
+				"file": file,
+				"questions": question_count
+			}));
+		}
+	}
+
+	/// Start of a question, only matters for the text output.
+	pub fn question(&mut self, question: &QuestionInfo) {
+		if self.format == Format::Text {
+			self.line(&format!(" {:>3}/{} '{}':", question.index, question.count, question.name));
+		}
+	}
+
+	pub fn note(&mut self, file: &str, question: &QuestionInfo, action: &str, note: &Note) {
+		if self.format == Format::Text {
+			let marker: &str = match note.severity {
+				Severity::Info => "",
+				Severity::Warning => "WARNING! ",
+				Severity::Error => "ERROR! "
+			};
+			match note.level {
+				0 => self.line(&format!("  {}", note.message)),
+				1 => self.line(&format!("   {}{}", marker, note.message)),
+				2 => self.line(&format!("    - {}{}", marker, note.message)),
+				_ => self.line(&format!("     + {}{}", marker, note.message))
+			}
+		} else {
+			self.record(json!({
+				"type": "note",
+				"file": file,
+				"question_index": question.index,
+				"question_name": question.name,
+				"qtype": question.qtype,
+				"action": action,
+				"severity": note.severity.as_str(),
+				"message": note.message,
+				"target": note.target,
+				"kind": note.kind
+			}));
+		}
+	}
+
+	pub fn diff(&mut self, file: &str, diff: &str) {
+		if self.format == Format::Text {
+			self.text(diff);
+		} else {
+			self.record(json!({
+				"type": "diff",
+				"file": file,
+				"diff": diff
+			}));
+		}
+	}
+
+	/// Changes that could not be made together, so none were made to the file.
+	pub fn conflict(&mut self, file: &str, question_name: &str, conflict: &Conflict) {
+		if self.format == Format::Text {
+			self.line(&format!("ERROR! Conflicting changes in question {} '{}', not changing '{}':", conflict.question, question_name, file));
+			self.line(&format!("    - {}", conflict.message()));
+		} else {
+			let side = |side: &ConflictSide| json!({
+				"action": side.action,
+				"field": side.field,
+				"start": side.range.0,
+				"end": side.range.1
+			});
+			self.record(json!({
+				"type": "conflict",
+				"file": file,
+				"question_index": conflict.question,
+				"question_name": question_name,
+				"first": side(&conflict.first),
+				"second": side(&conflict.second),
+				"nested": conflict.nested,
+				"message": conflict.message()
+			}));
+		}
+	}
+
+	/// Trouble outside of actions, e.g. writing failed.
+	pub fn error(&mut self, file: &str, message: &str) {
+		if self.format == Format::Text {
+			self.line(message);
+		} else {
+			self.record(json!({
+				"type": "error",
+				"file": file,
+				"message": message
+			}));
+		}
+	}
+
+	pub fn summary(&mut self, file_count: usize, skipped: &[Skipped]) {
+		if self.format == Format::Text {
+			if file_count > 1 {
+				self.line(&format!("\nProcessed {} files.", file_count));
+			}
+			if !skipped.is_empty() {
+				self.line(&format!("\nSkipped {} questions or files due to errors:", skipped.len()));
+				for skip in skipped {
+					match (&skip.question, &skip.action) {
+						(Some(question), Some(action)) => self.line(&format!(" {} '{}' (--{}): {}", skip.file, question, action, skip.message)),
+						(Some(question), None) => self.line(&format!(" {} '{}': {}", skip.file, question, skip.message)),
+						_ => self.line(&format!(" {}: {}", skip.file, skip.message))
+					}
+				}
+			}
+		} else {
+			let skipped: Vec<Value> = skipped.iter().map(|skip| json!({
+				"file": skip.file,
+				"question_name": skip.question,
+				"action": skip.action,
+				"message": skip.message
+			})).collect();
+			self.record(json!({
+				"type": "summary",
+				"files": file_count,
+				"skipped": skipped
+			}));
+		}
+	}
+
+	/// How the run compared to the baseline, `known` findings left out and
+	/// `fixed` ones of the baseline no longer found.
+	pub fn baseline(&mut self, path: &str, known: usize, fixed: usize) {
+		if self.format == Format::Text {
+			if known > 0 {
+				self.line(&format!("\n{} known findings from '{}' not reported.", known, path));
+			}
+			if fixed > 0 {
+				self.line(&format!("{} findings in '{}' are gone, --write-baseline to drop them.", fixed, path));
+			}
+		} else {
+			self.record(json!({
+				"type": "baseline",
+				"baseline": path,
+				"known": known,
+				"fixed": fixed
+			}));
+		}
+	}
+
+	/// Writing the baseline went fine.
+	pub fn baseline_written(&mut self, path: &str) {
+		if self.format == Format::Text {
+			self.line(&format!("\nFindings recorded to '{}'.", path));
+		} else {
+			self.record(json!({
+				"type": "baseline_written",
+				"baseline": path
+			}));
+		}
+	}
+
+	/// End report of an action.
+	pub fn report(&mut self, action: &str, name: &str, report: Option<String>, counters: &Counters) {
+		if self.format == Format::Text {
+			if let Some(r) = report {
+				self.line(&format!("\nEnd report from '{}'", name));
+				self.line(&r);
+			}
+		} else {
+			let mut values: Map<String, Value> = Map::new();
+			for (counter, value) in counters.iter() {
+				values.insert(counter.clone(), json!(value));
+			}
+			self.record(json!({
+				"type": "report",
+				"action": action,
+				"name": name,
+				"counters": values,
+				"summary": report
+			}));
+		}
+	}
+
+	/// Tells that the files are watched for changes to check again. Only in
+	/// the text format, scripts have the summary ending each pass instead.
+	pub fn watching(&mut self) {
+		if self.format == Format::Text {
+			self.line("\nWatching for changes, Ctrl+C to stop.\n");
+		}
+	}
+
+	/// Prints out whatever was collected.
+	pub fn finish(&mut self) {
+		if self.format == Format::Json {
+			self.line(&serde_json::to_string_pretty(&Value::Array(self.records.clone())).unwrap());
+			self.records.clear();
+		}
+	}
+}
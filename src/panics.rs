@@ -0,0 +1,43 @@
+//! Turning the panics of the parser library into errors.
+//!
+//! The library panics on content it does not expect, e.g. a STACK question
+//! missing an element that older exports do not have. That should only
+//! skip the question, so such calls go through `catch`, which also keeps
+//! the panic message off the terminal.
+
+use std::any::Any;
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+
+thread_local! {
+	/// Is this thread inside `catch`.
+	static CATCHING: Cell<bool> = const { Cell::new(false) };
+}
+
+static QUIET_HOOK: Once = Once::new();
+
+/// Runs `f`, returning the message of its panic as an error should it
+/// panic. Panics elsewhere are reported as usual.
+pub fn catch<T, F>(f: F) -> Result<T, String>
+where F: FnOnce() -> T {
+	QUIET_HOOK.call_once(|| {
+		let previous = panic::take_hook();
+		panic::set_hook(Box::new(move |info| {
+			if !CATCHING.with(|c| c.get()) {
+				previous(info);
+			}
+		}));
+	});
+	let was_catching: bool = CATCHING.with(|c| c.replace(true));
+	let result = panic::catch_unwind(AssertUnwindSafe(f));
+	CATCHING.with(|c| c.set(was_catching));
+	result.map_err(message)
+}
+
+fn message(payload: Box<dyn Any + Send>) -> String {
+	match payload.downcast::<String>() {
+		Ok(message) => *message,
+		Err(payload) => payload.downcast_ref::<&str>().map(|m| m.to_string()).unwrap_or("Unknown failure.".to_string())
+	}
+}
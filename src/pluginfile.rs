@@ -0,0 +1,191 @@
+//! Finding `@@PLUGINFILE@@` references to attached files in question
+//! content.
+//!
+//! References turn up in many forms: quoted or unquoted attribute values,
+//! `srcset` lists, CSS `url(...)`, strings in JSXGraph or GeoGebra code and
+//! escaped strings in Maxima. They may be HTML entity and URL encoded and
+//! carry `?` query or `#` fragment parts, which are not part of the name.
+
+use regex::Regex;
+use std::sync::OnceLock;
+use urlencoding::decode as url_decode;
+
+const MARKER: &str = "@@PLUGINFILE@@";
+
+/// A reference to an attached file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+	/// As written, from after the marker, e.g. "/a%20b.png?v=2".
+	pub raw: String,
+	/// The path and name of the file it refers to, e.g. "/a b.png".
+	pub name: String
+}
+
+/// Where a reference stops if it is not delimited by a quote or a
+/// parenthesis, or is in a list inside quotes: at whitespace followed by a
+/// `srcset` descriptor, or at a comma and whitespace separating items.
+fn list_separator() -> &'static Regex {
+	static SEPARATOR: OnceLock<Regex> = OnceLock::new();
+	SEPARATOR.get_or_init(|| Regex::new(r"\s+\d+(\.\d+)?[wx]\b|,\s|\s+@@PLUGINFILE@@").unwrap())
+}
+
+/// All references in the text, in order of appearance.
+pub fn references(text: &str) -> Vec<Reference> {
+	let mut result: Vec<Reference> = Vec::new();
+	for (position, _) in text.match_indices(MARKER) {
+		let start: usize = position + MARKER.len();
+		let rest: &str = &text[start..];
+		// The character before the marker tells what ends the reference.
+		let opener: Option<char> = text[..position].chars().next_back();
+		let end: usize = match opener {
+			Some(quote) if quote == '"' || quote == '\'' => rest.find(quote).unwrap_or(rest.len()),
+			Some('(') => rest.find(')').unwrap_or(rest.len()),
+			_ => rest.find(|c: char| c.is_whitespace() || "\"'<>()".contains(c)).unwrap_or(rest.len())
+		};
+		let mut raw: &str = &rest[..end];
+		if let Some(separator) = list_separator().find(raw) {
+			raw = &raw[..separator.start()];
+		}
+		// Escaped quotes in Maxima and JavaScript strings and the end of a
+		// list leave some extra behind.
+		raw = raw.trim_end_matches(['\\', ',']).trim_end();
+		if raw.is_empty() {
+			continue;
+		}
+		result.push(Reference {
+			raw: raw.to_string(),
+			name: file_name(raw)
+		});
+	}
+	result
+}
+
+/// The name a raw reference refers to: without the query and fragment,
+/// with JSON escaped slashes unescaped and decoded from HTML entities and
+/// URL encoding. Something that does not decode to UTF-8 is kept as it is.
+pub fn file_name(raw: &str) -> String {
+	let unescaped: String = raw.replace("\\/", "/");
+	let entities: String = decode_entities(&unescaped);
+	let without_query: &str = entities.split(['?', '#']).next().unwrap_or_default();
+	url_decode(without_query).map(|d| d.to_string()).unwrap_or(without_query.to_string())
+}
+
+/// Decodes the HTML entities likely to appear in file URLs.
+fn decode_entities(text: &str) -> String {
+	if !text.contains('&') {
+		return text.to_string();
+	}
+	let mut result: String = String::new();
+	let mut rest: &str = text;
+	while let Some(amp) = rest.find('&') {
+		result.push_str(&rest[..amp]);
+		rest = &rest[amp..];
+		let decoded: Option<(char, usize)> = rest.find(';').and_then(|semicolon| {
+			let entity: &str = &rest[1..semicolon];
+			let c: Option<char> = match entity {
+				"amp" => Some('&'),
+				"lt" => Some('<'),
+				"gt" => Some('>'),
+				"quot" => Some('"'),
+				"apos" => Some('\''),
+				"nbsp" => Some('\u{a0}'),
+				_ => match entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+					Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+					None => entity.strip_prefix('#').and_then(|dec| dec.parse::<u32>().ok()).and_then(char::from_u32)
+				}
+			};
+			c.map(|c| (c, semicolon + 1))
+		});
+		match decoded {
+			Some((c, length)) => {
+				result.push(c);
+				rest = &rest[length..];
+			},
+			None => {
+				result.push('&');
+				rest = &rest[1..];
+			}
+		}
+	}
+	result.push_str(rest);
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{file_name, references};
+
+	/// The names and the raw forms of the references in `text`.
+	fn found(text: &str) -> Vec<(String, String)> {
+		references(text).into_iter().map(|r| (r.name, r.raw)).collect()
+	}
+
+	fn pair(name: &str, raw: &str) -> (String, String) {
+		(name.to_string(), raw.to_string())
+	}
+
+	#[test]
+	fn double_quoted() {
+		assert_eq!(found(r#"<img src="@@PLUGINFILE@@/a b.png" alt="x">"#), vec![pair("/a b.png", "/a b.png")]);
+	}
+
+	#[test]
+	fn single_quoted() {
+		assert_eq!(found("<img src='@@PLUGINFILE@@/a.png'>"), vec![pair("/a.png", "/a.png")]);
+	}
+
+	#[test]
+	fn unquoted() {
+		assert_eq!(found("<img src=@@PLUGINFILE@@/a.png alt=x>"), vec![pair("/a.png", "/a.png")]);
+		assert_eq!(found("<img src=@@PLUGINFILE@@/a.png>"), vec![pair("/a.png", "/a.png")]);
+	}
+
+	#[test]
+	fn css_url() {
+		assert_eq!(found(r#"<div style="background: url(@@PLUGINFILE@@/bg.png) no-repeat">"#), vec![pair("/bg.png", "/bg.png")]);
+		assert_eq!(found("<style>p { background: url('@@PLUGINFILE@@/bg.png'); }</style>"), vec![pair("/bg.png", "/bg.png")]);
+	}
+
+	#[test]
+	fn srcset_lists() {
+		let text: &str = r#"<img srcset="@@PLUGINFILE@@/small.png 1x, @@PLUGINFILE@@/big.png 2x">"#;
+		assert_eq!(found(text), vec![pair("/small.png", "/small.png"), pair("/big.png", "/big.png")]);
+		let text: &str = r#"<img srcset="@@PLUGINFILE@@/a.png 480w,@@PLUGINFILE@@/b.png 800w">"#;
+		assert_eq!(found(text), vec![pair("/a.png", "/a.png"), pair("/b.png", "/b.png")]);
+	}
+
+	#[test]
+	fn jsxgraph_code() {
+		let text: &str = "[[jsxgraph]]board.create('image', ['@@PLUGINFILE@@/pic.png', [0, 0], [1, 1]]);[[/jsxgraph]]";
+		assert_eq!(found(text), vec![pair("/pic.png", "/pic.png")]);
+		// Escaped slashes in JSON.
+		assert_eq!(found(r#"{"url": "@@PLUGINFILE@@\/data\/a.json"}"#), vec![pair("/data/a.json", r"\/data\/a.json")]);
+	}
+
+	#[test]
+	fn query_and_fragment_are_not_part_of_the_name() {
+		assert_eq!(found(r#"<a href="@@PLUGINFILE@@/doc.pdf?forcedownload=1">"#), vec![pair("/doc.pdf", "/doc.pdf?forcedownload=1")]);
+		assert_eq!(found(r#"<a href="@@PLUGINFILE@@/doc.pdf#page=2">"#), vec![pair("/doc.pdf", "/doc.pdf#page=2")]);
+	}
+
+	#[test]
+	fn entities_and_url_encoding_are_decoded() {
+		assert_eq!(found(r#"<img src="@@PLUGINFILE@@/a&amp;b.png">"#), vec![pair("/a&b.png", "/a&amp;b.png")]);
+		assert_eq!(found(r#"<img src="@@PLUGINFILE@@/a%20b%C3%A4.png">"#), vec![pair("/a bä.png", "/a%20b%C3%A4.png")]);
+		assert_eq!(file_name("/x&#x41;&#66;&unknown;.png"), "/xAB&unknown;.png");
+		// Not UTF-8 once decoded, so kept as is.
+		assert_eq!(file_name("/a%FF.png"), "/a%FF.png");
+	}
+
+	#[test]
+	fn maxima_escaped_strings() {
+		let text: &str = r#"img: "<img src=\"@@PLUGINFILE@@/plot.png\">";"#;
+		assert_eq!(found(text), vec![pair("/plot.png", "/plot.png")]);
+	}
+
+	#[test]
+	fn nothing_after_the_marker_is_no_reference() {
+		assert!(found(r#"The marker "@@PLUGINFILE@@" on its own."#).is_empty());
+		assert!(found("No references here.").is_empty());
+	}
+}
@@ -0,0 +1,186 @@
+//! The known actions and the order in which they run.
+//!
+//! Actions run on each question in a stable order: an action runs after
+//! those it declares in `runs_after`, otherwise lower priority first and
+//! for equal priorities in the order of registration.
+
+use crate::action::Action;
+
+pub struct Registry {
+	actions: Vec<Box<dyn Action>>
+}
+
+impl Default for Registry {
+	fn default() -> Self {
+		Registry::new()
+	}
+}
+
+impl Registry {
+	pub fn new() -> Registry {
+		Registry {
+			actions: Vec::new()
+		}
+	}
+
+	/// A registry with all the actions shipped with the tool, in order.
+	pub fn with_builtin_actions() -> Result<Registry, String> {
+		let mut registry = Registry::new();
+		for action in crate::actions::builtin_actions() {
+			registry.register(action)?;
+		}
+		registry.resolve_order()?;
+		Ok(registry)
+	}
+
+	/// Adds an action, its flag must be unique.
+	pub fn register(&mut self, action: Box<dyn Action>) -> Result<(), String> {
+		let flag: String = action.flag();
+		if self.actions.iter().any(|a| a.flag() == flag) {
+			return Err(format!("An action with the flag '--{flag}' is already registered."));
+		}
+		self.actions.push(action);
+		Ok(())
+	}
+
+	/// Sorts the actions to their running order. Fails if an action
+	/// depends on something not registered or if the dependencies loop.
+	pub fn resolve_order(&mut self) -> Result<(), String> {
+		let flags: Vec<String> = self.actions.iter().map(|a| a.flag()).collect();
+		let mut dependencies: Vec<Vec<usize>> = Vec::new();
+		for action in &self.actions {
+			let mut deps: Vec<usize> = Vec::new();
+			for dep in action.runs_after() {
+				match flags.iter().position(|f| *f == dep) {
+					Some(i) => deps.push(i),
+					None => return Err(format!("Action '--{}' depends on unknown action '--{}'.", action.flag(), dep))
+				}
+			}
+			dependencies.push(deps);
+		}
+
+		let mut order: Vec<usize> = Vec::new();
+		let mut placed: Vec<bool> = vec![false; self.actions.len()];
+		while order.len() < self.actions.len() {
+			// Of those whose dependencies are in place, the one with the lowest priority.
+			let next: Option<usize> = (0..self.actions.len())
+				.filter(|i| !placed[*i] && dependencies[*i].iter().all(|d| placed[*d]))
+				.min_by_key(|i| (self.actions[*i].priority(), *i));
+			match next {
+				Some(i) => {
+					placed[i] = true;
+					order.push(i);
+				},
+				None => {
+					let stuck: Vec<String> = (0..self.actions.len()).filter(|i| !placed[*i]).map(|i| format!("--{}", flags[i])).collect();
+					return Err(format!("Circular dependencies between actions {}.", stuck.join(", ")));
+				}
+			}
+		}
+
+		let mut slots: Vec<Option<Box<dyn Action>>> = self.actions.drain(..).map(Some).collect();
+		for i in order {
+			if let Some(action) = slots[i].take() {
+				self.actions.push(action);
+			}
+		}
+		Ok(())
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = &Box<dyn Action>> {
+		self.actions.iter()
+	}
+
+	pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Box<dyn Action>> {
+		self.actions.iter_mut()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Registry;
+	use crate::action::{Action, ActionError, Context, Note};
+	use position_preserving_moodle_question_xml_edit::{Change, QParser, Question};
+
+	struct Named {
+		flag: &'static str,
+		priority: i32,
+		after: Vec<&'static str>
+	}
+
+	impl Action for Named {
+		fn process(&mut self, _question: &Question, _parser: &mut QParser, _context: &Context) -> Result<(Vec<Change>, Vec<Note>), ActionError> {
+			Ok((Vec::new(), Vec::new()))
+		}
+
+		fn name(&self) -> String {
+			self.flag.to_string()
+		}
+
+		fn flag(&self) -> String {
+			self.flag.to_string()
+		}
+
+		fn description(&self) -> String {
+			String::new()
+		}
+
+		fn supports(&self, _qtype: String) -> bool {
+			true
+		}
+
+		fn priority(&self) -> i32 {
+			self.priority
+		}
+
+		fn runs_after(&self) -> Vec<String> {
+			self.after.iter().map(|a| a.to_string()).collect()
+		}
+
+		fn report(&self) -> Option<String> {
+			None
+		}
+	}
+
+	fn registry(actions: Vec<(&'static str, i32, Vec<&'static str>)>) -> Registry {
+		let mut registry = Registry::new();
+		for (flag, priority, after) in actions {
+			registry.register(Box::new(Named { flag, priority, after })).unwrap();
+		}
+		registry
+	}
+
+	fn order(registry: &Registry) -> Vec<String> {
+		registry.iter().map(|a| a.flag()).collect()
+	}
+
+	#[test]
+	fn lower_priority_runs_first_and_ties_keep_their_order() {
+		let mut registry = registry(vec![("c", 1, vec![]), ("a", 0, vec![]), ("b", 0, vec![]), ("d", -1, vec![])]);
+		registry.resolve_order().unwrap();
+		assert_eq!(order(&registry), vec!["d", "a", "b", "c"]);
+	}
+
+	#[test]
+	fn dependencies_run_first_whatever_their_priority() {
+		let mut registry = registry(vec![("late", 5, vec![]), ("early", 0, vec!["late"]), ("other", 1, vec![])]);
+		registry.resolve_order().unwrap();
+		assert_eq!(order(&registry), vec!["other", "late", "early"]);
+	}
+
+	#[test]
+	fn flags_are_unique() {
+		let mut registry = registry(vec![("a", 0, vec![])]);
+		let err: String = registry.register(Box::new(Named { flag: "a", priority: 0, after: Vec::new() })).unwrap_err();
+		assert_eq!(err, "An action with the flag '--a' is already registered.");
+	}
+
+	#[test]
+	fn unknown_and_circular_dependencies_fail() {
+		let mut unknown = registry(vec![("a", 0, vec!["missing"])]);
+		assert_eq!(unknown.resolve_order().unwrap_err(), "Action '--a' depends on unknown action '--missing'.");
+
+		let mut circular = registry(vec![("free", 0, vec![]), ("a", 0, vec!["b"]), ("b", 0, vec!["a"])]);
+		assert_eq!(circular.resolve_order().unwrap_err(), "Circular dependencies between actions --a, --b.");
+	}
+}
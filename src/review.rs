@@ -0,0 +1,159 @@
+//! Deciding change by change whether a proposed change gets made, for
+//! `--interactive` and anyone else wanting a say before things are written.
+
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// One of the changes in a proposal.
+pub struct ProposedChange<'a> {
+	/// Where in the question the change is, e.g. "input[ans1]/tans".
+	pub field: String,
+	/// The byte range of the document that gets replaced.
+	pub range: (usize, usize),
+	pub new_content: &'a str
+}
+
+/// A change an action wants to make to a question, or several that only
+/// work together, e.g. a `tans` pointing to a variable added to the
+/// question variables. Those are decided on together.
+pub struct Proposal<'a> {
+	pub file: &'a str,
+	/// Flag of the action.
+	pub action: &'a str,
+	/// 1-based index of the question.
+	pub question: usize,
+	pub question_name: &'a str,
+	/// The document the changes apply to.
+	pub content: &'a str,
+	pub changes: Vec<ProposedChange<'a>>
+}
+
+impl Proposal<'_> {
+	/// The action and the fields changed without names, e.g.
+	/// "stacklang:input/tans+questionvariables/text", for deciding on all
+	/// similar changes at once.
+	pub fn kind(&self) -> String {
+		let mut fields: Vec<String> = self.changes.iter().map(|c| without_names(&c.field)).collect();
+		fields.sort();
+		fields.dedup();
+		format!("{}:{}", self.action, fields.join("+"))
+	}
+}
+
+/// "input[ans1]/tans" to "input/tans".
+fn without_names(field: &str) -> String {
+	let mut result: String = String::new();
+	let mut depth: usize = 0;
+	for c in field.chars() {
+		match c {
+			'[' => depth += 1,
+			']' => depth = depth.saturating_sub(1),
+			_ if depth == 0 => result.push(c),
+			_ => {}
+		}
+	}
+	result
+}
+
+/// Decides which changes are made.
+pub trait Review: Send {
+	/// Should the changes be made.
+	fn review(&mut self, proposal: &Proposal) -> bool;
+}
+
+/// Asks on the terminal about each change, showing it with a few lines
+/// around it.
+pub struct Interactive {
+	/// Kinds accepted without asking.
+	accepted_kinds: HashSet<String>,
+	quit: Arc<AtomicBool>
+}
+
+/// Lines of context to show around a change.
+const CONTEXT_LINES: usize = 2;
+
+impl Interactive {
+	/// `quit` gets set when the user wants no more changes, after which
+	/// everything is declined.
+	pub fn new(quit: Arc<AtomicBool>) -> Interactive {
+		Interactive {
+			accepted_kinds: HashSet::new(),
+			quit
+		}
+	}
+
+	fn show(&self, proposal: &Proposal) {
+		println!("\n{} question {} '{}', --{} wants to change:", proposal.file, proposal.question, proposal.question_name, proposal.action);
+		if proposal.changes.len() > 1 {
+			println!(" (These only work together, so they are made or left out together.)");
+		}
+		let content: &str = proposal.content;
+		for change in &proposal.changes {
+			let (start, end) = (change.range.0.min(content.len()), change.range.1.min(content.len()));
+			// Whole lines of the change and the context around them.
+			let line_start: usize = content[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+			let line_end: usize = content[end..].find('\n').map(|i| end + i).unwrap_or(content.len());
+			let before: Vec<&str> = content[..line_start].lines().rev().take(CONTEXT_LINES).collect();
+			let after: Vec<&str> = content[line_end..].lines().skip(1).take(CONTEXT_LINES).collect();
+			let replaced: String = format!("{}{}{}", &content[line_start..start], change.new_content, &content[end..line_end]);
+
+			println!(" {}:", change.field);
+			for line in before.iter().rev() {
+				println!("  {}", line);
+			}
+			for line in content[line_start..line_end].lines() {
+				println!("- {}", line);
+			}
+			for line in replaced.lines() {
+				println!("+ {}", line);
+			}
+			for line in after {
+				println!("  {}", line);
+			}
+		}
+	}
+}
+
+impl Review for Interactive {
+	fn review(&mut self, proposal: &Proposal) -> bool {
+		if self.quit.load(Ordering::Relaxed) {
+			return false;
+		}
+		let kind: String = proposal.kind();
+		if self.accepted_kinds.contains(&kind) {
+			return true;
+		}
+		self.show(proposal);
+		let question: &str = if proposal.changes.len() > 1 {
+			"Make these changes together?"
+		} else {
+			"Make this change?"
+		};
+		loop {
+			print!("{} [y]es, [n]o, [a]ll of '{}', [q]uit: ", question, kind);
+			let _ = std::io::stdout().flush();
+			let mut answer: String = String::new();
+			if std::io::stdin().lock().read_line(&mut answer).unwrap_or(0) == 0 {
+				// Nobody to ask anymore.
+				println!();
+				self.quit.store(true, Ordering::Relaxed);
+				return false;
+			}
+			match answer.trim().to_lowercase().as_str() {
+				"y" | "yes" => return true,
+				"n" | "no" => return false,
+				"a" | "all" => {
+					self.accepted_kinds.insert(kind);
+					return true;
+				},
+				"q" | "quit" => {
+					self.quit.store(true, Ordering::Relaxed);
+					return false;
+				},
+				_ => {}
+			}
+		}
+	}
+}
@@ -0,0 +1,437 @@
+//! Runs the selected actions over the questions of a file and returns
+//! what happened as data, leaving printing and saving to the caller.
+
+use position_preserving_moodle_question_xml_edit::{QParser, Question};
+use crate::action::{Action, ActionError, Context, Note, Severity};
+use crate::changes::{field_at, ChangeSet, Conflict, PendingChange};
+use crate::inputs::read_input;
+use crate::panics;
+use crate::registry::Registry;
+use crate::review::{Proposal, ProposedChange, Review};
+use crate::select::{question_meta, QuestionMeta, Selection};
+use crate::status::Status;
+use similar::TextDiff;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+/// A note together with the flag of the action that made it.
+#[derive(Debug, Clone)]
+pub struct ActionNote {
+	pub action: String,
+	pub note: Note
+}
+
+/// What the actions had to say about a single question.
+#[derive(Debug, Clone)]
+pub struct QuestionResult {
+	/// 1-based position in the file.
+	pub index: usize,
+	pub name: String,
+	pub qtype: String,
+	pub category: Option<String>,
+	pub idnumber: Option<String>,
+	pub notes: Vec<ActionNote>,
+	/// Did any action find something to change.
+	pub changed: bool,
+	/// The flag of the action that failed and the reason, the actions
+	/// after it were not run for this question.
+	pub failure: Option<(String, String)>
+}
+
+impl QuestionResult {
+	pub fn status(&self) -> Status {
+		let mut status: Status = Status::Clean;
+		if self.changed {
+			status = status.with(Status::Changes);
+		}
+		if self.notes.iter().any(|n| n.note.severity >= Severity::Warning) {
+			status = status.with(Status::Warnings);
+		}
+		if self.failure.is_some() || self.notes.iter().any(|n| n.note.severity == Severity::Error) {
+			status = status.with(Status::Error);
+		}
+		status
+	}
+}
+
+/// The outcome of processing a whole file.
+#[derive(Debug, Clone)]
+pub struct FileResult {
+	pub file: String,
+	/// All questions in the file, selected or not.
+	pub question_count: usize,
+	/// Only the selected questions.
+	pub questions: Vec<QuestionResult>,
+	/// The content as loaded.
+	pub original: String,
+	/// The content with the changes applied, only present if something
+	/// changed, the context wanted changes and they did not overlap.
+	pub modified: Option<String>,
+	/// Changes that could not be made together, if any none were made.
+	pub conflicts: Vec<Conflict>
+}
+
+impl FileResult {
+	pub fn changed(&self) -> bool {
+		self.questions.iter().any(|q| q.changed)
+	}
+
+	/// Unified diff from the original to the modified content.
+	pub fn diff(&self) -> Option<String> {
+		self.modified.as_ref().map(|modified| TextDiff::from_lines(&self.original, modified)
+			.unified_diff()
+			.context_radius(3)
+			.header(&self.file, &self.file)
+			.to_string())
+	}
+
+	pub fn status(&self) -> Status {
+		let status: Status = self.questions.iter().fold(Status::Clean, |s, q| s.with(q.status()));
+		if self.conflicts.is_empty() {
+			status
+		} else {
+			status.with(Status::Error)
+		}
+	}
+}
+
+/// Owns the configured actions and runs the selected ones.
+pub struct Runner {
+	actions: Registry,
+	selected: Vec<String>,
+	context: Context,
+	selection: Selection,
+	/// Severities by "<action flag>.<note kind>".
+	severities: HashMap<String, Severity>,
+	/// Only act on questions changed since this git revision.
+	changed_since: Option<String>,
+	/// Gets to decide on each change before it is registered.
+	review: Option<Box<dyn Review>>
+}
+
+impl Runner {
+	/// The actions should already be configured, `selected` lists the flags
+	/// of those to run.
+	pub fn new(actions: Registry, selected: Vec<String>, context: Context) -> Runner {
+		Runner {
+			actions,
+			selected,
+			context,
+			selection: Selection::default(),
+			severities: HashMap::new(),
+			changed_since: None,
+			review: None
+		}
+	}
+
+	/// Limits the questions acted on, by default all are.
+	pub fn with_selection(mut self, selection: Selection) -> Runner {
+		self.selection = selection;
+		self
+	}
+
+	pub fn context(&self) -> &Context {
+		&self.context
+	}
+
+	/// The selected actions in their running order, e.g. for end reports.
+	pub fn selected_actions(&self) -> impl Iterator<Item = &Box<dyn Action>> {
+		self.actions.iter().filter(|a| self.selected.contains(&a.flag()))
+	}
+
+	/// Replaces the severity of notes, keyed by "<action flag>.<note kind>".
+	pub fn with_severities(mut self, severities: HashMap<String, Severity>) -> Runner {
+		self.severities = severities;
+		self
+	}
+
+	/// Limits the questions acted on to those whose lines have changed
+	/// since the git revision, on top of the selection.
+	pub fn with_changed_since(mut self, rev: &str) -> Runner {
+		self.changed_since = Some(rev.to_string());
+		self
+	}
+
+	/// Has each change reviewed before it is made, the declined ones are
+	/// left out.
+	pub fn with_review(mut self, review: Box<dyn Review>) -> Runner {
+		self.review = Some(review);
+		self
+	}
+
+	/// Takes in what the actions of another runner have seen.
+	pub fn merge(&mut self, other: &Runner) {
+		for action in self.actions.iter_mut() {
+			if let Some(theirs) = other.actions.iter().find(|a| a.flag() == action.flag()) {
+				action.merge(theirs.as_ref());
+			}
+		}
+	}
+
+	/// Processes many files, this runner and each of the `helpers` working
+	/// on their own thread. The helpers should be configured like this one
+	/// and are merged into it at the end.
+	///
+	/// The results are handed to `handle` on the calling thread in the order
+	/// of `files`, so it may print and write as it likes. Returning false
+	/// from it stops the processing.
+	pub fn process_files<F>(&mut self, files: &[String], mut helpers: Vec<Runner>, mut handle: F)
+	where F: FnMut(&str, Result<FileResult, String>) -> bool {
+		let next: AtomicUsize = AtomicUsize::new(0);
+		let stop: AtomicBool = AtomicBool::new(false);
+		let (sender, receiver) = mpsc::channel::<(usize, Result<FileResult, String>)>();
+		thread::scope(|scope| {
+			let mut workers: Vec<&mut Runner> = vec![&mut *self];
+			workers.extend(helpers.iter_mut());
+			for worker in workers {
+				let sender = sender.clone();
+				let (next, stop) = (&next, &stop);
+				scope.spawn(move || {
+					while !stop.load(Ordering::Relaxed) {
+						let i: usize = next.fetch_add(1, Ordering::Relaxed);
+						if i >= files.len() || sender.send((i, worker.process_file(&files[i]))).is_err() {
+							break;
+						}
+					}
+				});
+			}
+			drop(sender);
+
+			// Results come in any order, hold them until those before are out.
+			let mut pending: BTreeMap<usize, Result<FileResult, String>> = BTreeMap::new();
+			let mut next_out: usize = 0;
+			for (i, result) in receiver.iter() {
+				pending.insert(i, result);
+				while let Some(result) = pending.remove(&next_out) {
+					let go_on: bool = handle(&files[next_out], result);
+					next_out += 1;
+					if !go_on {
+						stop.store(true, Ordering::Relaxed);
+						return;
+					}
+				}
+			}
+		});
+		for helper in &helpers {
+			self.merge(helper);
+		}
+	}
+
+	/// Reads and processes a file, `-` being the standard input. Errors if
+	/// the file cannot be read or parsed, problems with single questions are
+	/// in the results.
+	pub fn process_file(&mut self, file_name: &str) -> Result<FileResult, String> {
+		let content: String = read_input(file_name)?;
+		self.process_content(file_name, content)
+	}
+
+	/// Processes the content of a file already read.
+	pub fn process_content(&mut self, file_name: &str, content: String) -> Result<FileResult, String> {
+		let mut parser: QParser = QParser::from_string(content)
+			.map_err(|e| format!("Could not parse '{}': {}", file_name, e))?;
+		let original: String = parser.get_current_content();
+		let question_count: usize = parser.find_questions().len();
+		let changed: Option<Vec<(usize, usize)>> = match &self.changed_since {
+			Some(rev) => Some(crate::git::changed_ranges(rev, file_name, &original)?),
+			None => None
+		};
+		let (questions, conflicts) = self.process_within(&mut parser, file_name, changed.as_deref());
+		let mut modified: Option<String> = None;
+		if self.context.changes_wanted() && conflicts.is_empty() && questions.iter().any(|q| q.changed) {
+			parser.execute_changes();
+			modified = Some(parser.get_current_content());
+		}
+		Ok(FileResult {
+			file: file_name.to_string(),
+			question_count,
+			questions,
+			original,
+			modified,
+			conflicts
+		})
+	}
+
+	/// Runs the selected actions on the selected questions in the parser.
+	/// If the context wants changes, they are made in the parser, unless
+	/// they overlap, in which case none are and the overlaps are returned.
+	pub fn process(&mut self, parser: &mut QParser) -> (Vec<QuestionResult>, Vec<Conflict>) {
+		self.process_within(parser, "", None)
+	}
+
+	/// How many of the selected actions have to run before each action,
+	/// one after another, by flag. Those of one level run together, and the
+	/// changes of the levels before are made first.
+	fn levels(&self) -> HashMap<String, usize> {
+		let mut levels: HashMap<String, usize> = HashMap::new();
+		// The registry has them in order, so dependencies come first.
+		for action in self.actions.iter().filter(|a| self.selected.contains(&a.flag())) {
+			let level: usize = action.runs_after().iter()
+				.filter_map(|dep| levels.get(dep))
+				.map(|level| level + 1)
+				.max()
+				.unwrap_or(0);
+			levels.insert(action.flag(), level);
+		}
+		levels
+	}
+
+	/// Like `process`, but if `changed` is given only questions touching
+	/// one of those byte ranges are selected. An empty range marks removed
+	/// content and touches the questions it is in or next to.
+	fn process_within(&mut self, parser: &mut QParser, file_name: &str, changed: Option<&[(usize, usize)]>) -> (Vec<QuestionResult>, Vec<Conflict>) {
+		let mut results: Vec<QuestionResult> = Vec::new();
+		// Changes made and declined on review, by question and action.
+		let mut reviewed: BTreeMap<(usize, String), (usize, usize)> = BTreeMap::new();
+		let mut questions: Vec<Question> = parser.find_questions();
+		let mut pending: ChangeSet = ChangeSet::new(parser);
+		// The parser has already accepted the document, so this will parse.
+		let metas: Vec<QuestionMeta> = question_meta(&parser.get_current_content()).unwrap_or_default();
+		let context: Context = Context {
+			file: Some(file_name.to_string()).filter(|f| !f.is_empty()),
+			..self.context.clone()
+		};
+		for (qi, question) in questions.iter().enumerate() {
+			let meta: QuestionMeta = metas.get(qi).cloned().unwrap_or_default();
+			if !self.selection.is_everything() && !self.selection.matches(qi + 1, &meta) {
+				continue;
+			}
+			if let Some(ranges) = changed {
+				let (start, end) = meta.range;
+				if !ranges.iter().any(|(s, e)| if s == e { start <= *s && *s <= end } else { *s < end && start < *e }) {
+					continue;
+				}
+			}
+			results.push(QuestionResult {
+				index: qi + 1,
+				name: question.name.unwrap_cdata(),
+				qtype: question.qtype.clone(),
+				category: meta.category,
+				idnumber: meta.idnumber,
+				notes: Vec::new(),
+				changed: false,
+				failure: None
+			});
+		}
+
+		let levels: HashMap<String, usize> = self.levels();
+		let deepest: usize = levels.values().copied().max().unwrap_or(0);
+		for level in 0..=deepest {
+			if level > 0 && self.context.changes_wanted() && !pending.is_empty() && !pending.has_conflicts() {
+				// The actions of this level want to see the changes, so make them and re-index.
+				if let Some(review) = &mut self.review {
+					review_changes(review.as_mut(), file_name, &mut pending, &questions, &mut reviewed);
+				}
+				if pending.register(parser).is_ok() {
+					questions = parser.find_questions();
+				}
+			}
+			for result in results.iter_mut().filter(|r| r.failure.is_none()) {
+				let qi: usize = result.index - 1;
+				let question: &Question = match questions.get(qi) {
+					Some(question) => question,
+					None => continue
+				};
+				for action in self.actions.iter_mut() {
+					if levels.get(&action.flag()) != Some(&level) || !action.supports(question.qtype.clone()) {
+						continue;
+					}
+					// The parser panics on questions it cannot make sense of.
+					let outcome = panics::catch(|| action.process(question, parser, &context))
+						.unwrap_or_else(|message| Err(ActionError::new(format!("The question could not be read: {}", message))));
+					match outcome {
+						Ok((changes, notes)) => {
+							if !changes.is_empty() {
+								result.changed = true;
+								pending.add(&action.flag(), qi + 1, changes, &action.linked_changes());
+							}
+							for mut note in notes {
+								if let Some(severity) = note.kind.as_ref().and_then(|kind| self.severities.get(&format!("{}.{}", action.flag(), kind))) {
+									note.severity = *severity;
+								}
+								result.notes.push(ActionNote {
+									action: action.flag(),
+									note
+								});
+							}
+						},
+						Err(e) => {
+							// Report and skip the rest of the actions for this question.
+							result.notes.push(ActionNote {
+								action: action.flag(),
+								note: Note::error(format!("Skipping question: {}", e)).of_kind("skipped_question")
+							});
+							result.failure = Some((action.flag(), e.message));
+							break;
+						}
+					}
+				}
+			}
+		}
+
+		if let (true, Some(review)) = (self.context.changes_wanted(), &mut self.review) {
+			review_changes(review.as_mut(), file_name, &mut pending, &questions, &mut reviewed);
+		}
+		let conflicts: Vec<Conflict> = pending.conflicts();
+		if self.context.changes_wanted() && conflicts.is_empty() {
+			// Checked above, so this makes everything.
+			let _ = pending.register(parser);
+		}
+		for result in results.iter_mut() {
+			let counts: Vec<(&String, &(usize, usize))> = reviewed.iter().filter(|((q, _), _)| *q == result.index).map(|((_, a), c)| (a, c)).collect();
+			if counts.is_empty() {
+				continue;
+			}
+			result.changed = counts.iter().any(|(_, (made, _))| *made > 0);
+			for (action, (_, declined)) in counts.into_iter().filter(|(_, (_, declined))| *declined > 0) {
+				result.notes.push(ActionNote {
+					action: action.clone(),
+					note: Note::info(format!("Left out {} changes on review.", declined)).of_kind("declined")
+				});
+			}
+		}
+		(results, conflicts)
+	}
+}
+
+/// Drops the changes the review declines, counting the made and declined
+/// ones in `reviewed`. Each change is decided on by itself, unless it only
+/// works together with others.
+fn review_changes(review: &mut dyn Review, file_name: &str, pending: &mut ChangeSet, questions: &[Question], reviewed: &mut BTreeMap<(usize, String), (usize, usize)>) {
+	let content: String = pending.content().to_string();
+	let mut groups: Vec<(usize, Vec<&PendingChange>)> = Vec::new();
+	for p in pending.iter() {
+		match groups.iter_mut().find(|(group, _)| *group == p.group) {
+			Some((_, changes)) => changes.push(p),
+			None => groups.push((p.group, vec![p]))
+		}
+	}
+
+	let mut accepted: Vec<usize> = Vec::new();
+	for (group, changes) in groups {
+		let (question, action): (usize, &str) = (changes[0].question, &changes[0].action);
+		let question_name: String = questions.get(question - 1).map(|q| q.name.unwrap_cdata()).unwrap_or_default();
+		let proposal = Proposal {
+			file: file_name,
+			action,
+			question,
+			question_name: &question_name,
+			content: &content,
+			changes: changes.iter().map(|p| ProposedChange {
+				field: field_at(&content, p.range),
+				range: p.range,
+				new_content: &p.change.new_content
+			}).collect()
+		};
+		let made: bool = review.review(&proposal);
+		let counts: &mut (usize, usize) = reviewed.entry((question, action.to_string())).or_insert((0, 0));
+		if made {
+			counts.0 += changes.len();
+			accepted.push(group);
+		} else {
+			counts.1 += changes.len();
+		}
+	}
+	pending.retain(|p| accepted.contains(&p.group));
+}
@@ -0,0 +1,56 @@
+//! Saving modified files so that neither a crash nor a bad change leaves
+//! a damaged file behind.
+//!
+//! The new content is first checked to still be a question file with the
+//! same questions, then written to a temporary file next to the target
+//! and renamed over it, which replaces the file in one step.
+
+use position_preserving_moodle_question_xml_edit::{QParser, Question};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Checks that the modified content parses and has the same questions,
+/// by name and type and in the same order, as the original.
+pub fn verify(original: &str, modified: &str) -> Result<(), String> {
+	let before: Vec<(String, String)> = questions_of(original)
+		.map_err(|e| format!("The original does not parse: {}", e))?;
+	let after: Vec<(String, String)> = questions_of(modified)
+		.map_err(|e| format!("The changes would break the XML: {}", e))?;
+	if before.len() != after.len() {
+		return Err(format!("The changes would turn {} questions to {}.", before.len(), after.len()));
+	}
+	for (i, (b, a)) in before.iter().zip(after.iter()).enumerate() {
+		if b != a {
+			return Err(format!("The changes would turn question {} '{}' ({}) to '{}' ({}).", i + 1, b.0, b.1, a.0, a.1));
+		}
+	}
+	Ok(())
+}
+
+fn questions_of(content: &str) -> Result<Vec<(String, String)>, String> {
+	let mut parser: QParser = QParser::from_string(content.to_string()).map_err(|e| e.to_string())?;
+	let questions: Vec<Question> = parser.find_questions();
+	Ok(questions.iter().map(|q| (q.name.unwrap_cdata(), q.qtype.clone())).collect())
+}
+
+/// Writes through a temporary file in the same directory and renames it
+/// over the target, keeping the permissions of the target.
+pub fn write_atomic(file: &str, content: &str) -> Result<(), String> {
+	let target: &Path = Path::new(file);
+	let name: String = target.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+	// Hidden and not ending with `.xml` so that nothing picks it up.
+	let temp: PathBuf = target.with_file_name(format!(".{}.tmp-{}", name, std::process::id()));
+	let written = fs::File::create(&temp).and_then(|mut f| {
+		f.write_all(content.as_bytes())?;
+		if let Ok(meta) = fs::metadata(target) {
+			f.set_permissions(meta.permissions())?;
+		}
+		f.sync_all()
+	}).and_then(|_| fs::rename(&temp, target));
+	if let Err(e) = written {
+		let _ = fs::remove_file(&temp);
+		return Err(format!("Could not write '{}': {}", file, e));
+	}
+	Ok(())
+}
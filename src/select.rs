@@ -0,0 +1,199 @@
+//! Picking the questions to act on, by name, category, type, idnumber or
+//! position in the file. Questions not selected are left untouched.
+
+use crate::options::OptionValues;
+use regex::Regex;
+
+/// Details of a question not directly available from the parser.
+#[derive(Debug, Clone, Default)]
+pub struct QuestionMeta {
+	pub name: String,
+	pub qtype: String,
+	/// From the closest preceding category "question", if any.
+	pub category: Option<String>,
+	pub idnumber: Option<String>,
+	/// Byte range of the question element in the document.
+	pub range: (usize, usize)
+}
+
+/// Lists the questions of a document in the same order as
+/// `QParser::find_questions`, i.e. skipping categories and typeless ones.
+pub fn question_meta(content: &str) -> Result<Vec<QuestionMeta>, String> {
+	let options = roxmltree::ParsingOptions {
+		allow_dtd: true,
+		..roxmltree::ParsingOptions::default()
+	};
+	let doc = roxmltree::Document::parse_with_options(content, options).map_err(|e| e.to_string())?;
+	let mut result: Vec<QuestionMeta> = Vec::new();
+	let mut category: Option<String> = None;
+	for node in doc.descendants().filter(|n| n.has_tag_name("question")) {
+		let qtype: &str = match node.attribute("type") {
+			Some(qtype) => qtype,
+			None => continue
+		};
+		if qtype == "category" {
+			category = child_text(node, &["category", "text"]);
+			continue;
+		}
+		result.push(QuestionMeta {
+			name: child_text(node, &["name", "text"]).unwrap_or_default(),
+			qtype: qtype.to_string(),
+			category: category.clone(),
+			idnumber: child_text(node, &["idnumber"]).filter(|i| !i.is_empty()),
+			range: (node.range().start, node.range().end)
+		});
+	}
+	Ok(result)
+}
+
+/// The text of a nested child element, following the given tag names.
+fn child_text(node: roxmltree::Node, path: &[&str]) -> Option<String> {
+	let mut current = node;
+	for tag in path {
+		current = current.children().find(|c| c.has_tag_name(*tag))?;
+	}
+	let text: String = current.descendants().filter(|n| n.is_text()).filter_map(|n| n.text()).collect();
+	Some(text.trim().to_string())
+}
+
+/// The question selectors given on the command line. The default selects
+/// everything.
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+	name: Option<Regex>,
+	categories: Vec<String>,
+	types: Vec<String>,
+	idnumbers: Vec<String>,
+	/// 1-based and inclusive.
+	index: Option<(usize, usize)>
+}
+
+impl Selection {
+	/// Reads the `--only-*` and `--index` options.
+	pub fn from_options(values: &OptionValues) -> Result<Selection, String> {
+		let name: Option<Regex> = match values.text("only-name") {
+			Some(pattern) => Some(Regex::new(&pattern).map_err(|e| format!("--only-name is not a valid regular expression: {}", e))?),
+			None => None
+		};
+		let index: Option<(usize, usize)> = match values.text("index") {
+			Some(range) => Some(parse_range(&range).ok_or(format!("--index expects a range like '3..10', '3..', '..10' or '7', got '{range}'."))?),
+			None => None
+		};
+		Ok(Selection {
+			name,
+			categories: values.list("only-category").iter().map(|c| c.trim_matches('/').to_string()).collect(),
+			types: split_commas(&values.list("only-type")),
+			idnumbers: split_commas(&values.list("only-idnumber")),
+			index
+		})
+	}
+
+	/// Does this select everything.
+	pub fn is_everything(&self) -> bool {
+		self.name.is_none() && self.categories.is_empty() && self.types.is_empty() && self.idnumbers.is_empty() && self.index.is_none()
+	}
+
+	/// `index` is the 1-based position of the question in its file.
+	pub fn matches(&self, index: usize, question: &QuestionMeta) -> bool {
+		if let Some((first, last)) = self.index {
+			if index < first || index > last {
+				return false;
+			}
+		}
+		if let Some(name) = &self.name {
+			if !name.is_match(&question.name) {
+				return false;
+			}
+		}
+		if !self.types.is_empty() && !self.types.contains(&question.qtype) {
+			return false;
+		}
+		if !self.idnumbers.is_empty() && !question.idnumber.as_ref().is_some_and(|i| self.idnumbers.contains(i)) {
+			return false;
+		}
+		if !self.categories.is_empty() && !question.category.as_ref().is_some_and(|c| self.categories.iter().any(|wanted| in_category(c, wanted))) {
+			return false;
+		}
+		true
+	}
+}
+
+/// Is the category the wanted one or below it. The context part, e.g.
+/// `$course$/`, may be left out of the wanted one.
+fn in_category(category: &str, wanted: &str) -> bool {
+	let category: &str = category.trim_matches('/');
+	let without_context: &str = match category.strip_prefix('$').and_then(|c| c.split_once("$/")) {
+		Some((_, rest)) => rest,
+		None => category
+	};
+	[category, without_context].iter().any(|c| *c == wanted || c.starts_with(&format!("{wanted}/")))
+}
+
+fn split_commas(values: &[String]) -> Vec<String> {
+	values.iter().flat_map(|v| v.split(',')).map(|v| v.trim().to_string()).filter(|v| !v.is_empty()).collect()
+}
+
+/// "3..10", "3..", "..10" or "7", 1-based and inclusive.
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+	let bound = |s: &str, default: usize| if s.trim().is_empty() { Some(default) } else { s.trim().parse::<usize>().ok() };
+	let (first, last) = match range.split_once("..") {
+		Some((first, last)) => (bound(first, 1)?, bound(last, usize::MAX)?),
+		None => {
+			let single: usize = range.trim().parse::<usize>().ok()?;
+			(single, single)
+		}
+	};
+	if first == 0 || first > last {
+		return None;
+	}
+	Some((first, last))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{parse_range, QuestionMeta, Selection};
+	use crate::options::{OptionKind, OptionSpec, OptionValues};
+
+	fn index(range: &str) -> Result<Selection, String> {
+		let spec = OptionSpec::new("index", OptionKind::Text, "");
+		let mut values = OptionValues::new();
+		values.set(&spec, vec![range.to_string()]);
+		Selection::from_options(&values)
+	}
+
+	#[test]
+	fn ranges_may_be_open_at_either_end() {
+		assert_eq!(parse_range("3..10"), Some((3, 10)));
+		assert_eq!(parse_range("3.."), Some((3, usize::MAX)));
+		assert_eq!(parse_range("..10"), Some((1, 10)));
+		assert_eq!(parse_range(".."), Some((1, usize::MAX)));
+		assert_eq!(parse_range(" 2 .. 4 "), Some((2, 4)));
+		assert_eq!(parse_range("7"), Some((7, 7)));
+	}
+
+	#[test]
+	fn reversed_zero_and_non_numeric_ranges_are_refused() {
+		assert_eq!(parse_range("10..3"), None);
+		assert_eq!(parse_range("0"), None);
+		assert_eq!(parse_range("0..5"), None);
+		assert_eq!(parse_range("..0"), None);
+		assert_eq!(parse_range("first"), None);
+		assert_eq!(parse_range("1..x"), None);
+		assert_eq!(parse_range("-1..3"), None);
+		assert_eq!(parse_range(""), None);
+	}
+
+	#[test]
+	fn bad_ranges_are_explained() {
+		for range in ["10..3", "0", "x"] {
+			assert_eq!(index(range).err().unwrap(), format!("--index expects a range like '3..10', '3..', '..10' or '7', got '{range}'."));
+		}
+	}
+
+	#[test]
+	fn the_range_is_inclusive() {
+		let selection: Selection = index("2..3").unwrap();
+		let meta = QuestionMeta::default();
+		assert_eq!((1..=4).map(|i| selection.matches(i, &meta)).collect::<Vec<bool>>(), vec![false, true, true, false]);
+	}
+}
@@ -0,0 +1,54 @@
+//! The overall outcome of a run and how it maps to the process exit code.
+//!
+//! The exit codes are:
+//!  0 clean, or nothing at or above the `--fail-on` level
+//!  1 changes were or would be made
+//!  2 warnings were found
+//!  3 a hard error, e.g. a file could not be written
+//!
+//! By default only hard errors cause a non-zero exit code, so that plain
+//! `--write` runs in scripts do not fail because they did something.
+
+/// Ordered so that the worst outcome seen is the maximum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+	Clean,
+	Changes,
+	Warnings,
+	Error
+}
+
+impl Status {
+	pub fn code(&self) -> i32 {
+		match self {
+			Status::Clean => 0,
+			Status::Changes => 1,
+			Status::Warnings => 2,
+			Status::Error => 3
+		}
+	}
+
+	/// Combines this with something newly seen.
+	pub fn with(self, other: Status) -> Status {
+		self.max(other)
+	}
+
+	/// The exit code, given the lowest status that should fail the run.
+	pub fn exit_code(&self, fail_on: Status) -> i32 {
+		if *self >= fail_on {
+			self.code()
+		} else {
+			0
+		}
+	}
+
+	/// Parses the value of `--fail-on`.
+	pub fn fail_on_from_name(name: &str) -> Option<Status> {
+		match name {
+			"change" => Some(Status::Changes),
+			"warning" => Some(Status::Warnings),
+			"error" => Some(Status::Error),
+			_ => None
+		}
+	}
+}
@@ -0,0 +1,76 @@
+//! Watching the given files and directories for changes, so that the
+//! checks can be re-run as authors save their work.
+//!
+//! Uses inotify on Linux and whatever the platform offers elsewhere.
+//! Editors tend to save in several steps, so events are collected until
+//! things have been quiet for a moment before reporting the files.
+
+use crate::inputs::{InputFilter, STDIN};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// How long to wait for more events after one.
+const QUIET_PERIOD: Duration = Duration::from_millis(200);
+
+/// Watches `paths` until the watcher fails, calling `on_change` with the
+/// files that were created or modified and would be picked up by `filter`,
+/// in the order of `paths`.
+pub fn watch<F>(paths: &[String], filter: &InputFilter, mut on_change: F) -> Result<(), String>
+where F: FnMut(&[String]) {
+	let (sender, receiver) = mpsc::channel::<notify::Result<Event>>();
+	let mut watcher: RecommendedWatcher = notify::recommended_watcher(sender)
+		.map_err(|e| format!("Could not start watching: {}", e))?;
+	// The arguments as given and as canonical paths for matching the events.
+	let mut roots: Vec<(String, PathBuf)> = Vec::new();
+	for path in paths.iter().filter(|p| *p != STDIN) {
+		let canonical: PathBuf = std::fs::canonicalize(path)
+			.map_err(|e| format!("Could not watch '{}': {}", path, e))?;
+		// Editors often save by replacing the file, which would end the
+		// watch of the file itself, so files are watched via their directory.
+		let watched = match canonical.parent() {
+			Some(parent) if canonical.is_file() => watcher.watch(parent, RecursiveMode::NonRecursive),
+			_ => watcher.watch(&canonical, RecursiveMode::Recursive)
+		};
+		watched.map_err(|e| format!("Could not watch '{}': {}", path, e))?;
+		roots.push((path.clone(), canonical));
+	}
+
+	loop {
+		let first = receiver.recv().map_err(|e| format!("Watching stopped: {}", e))?;
+		let mut changed: Vec<PathBuf> = Vec::new();
+		let mut take = |event: notify::Result<Event>| -> Result<(), String> {
+			let event: Event = event.map_err(|e| format!("Watching failed: {}", e))?;
+			if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+				changed.extend(event.paths.into_iter().filter_map(|p| std::fs::canonicalize(p).ok()));
+			}
+			Ok(())
+		};
+		take(first)?;
+		while let Ok(event) = receiver.recv_timeout(QUIET_PERIOD) {
+			take(event)?;
+		}
+
+		let mut files: Vec<String> = Vec::new();
+		for (given, root) in &roots {
+			for file in changed.iter().filter(|f| f.is_file() && filter.accepts(root, f)) {
+				let name: String = display_name(given, root, file);
+				if !files.contains(&name) {
+					files.push(name);
+				}
+			}
+		}
+		if !files.is_empty() {
+			on_change(&files);
+		}
+	}
+}
+
+/// Names the file like walking the given argument would have.
+fn display_name(given: &str, root: &Path, file: &Path) -> String {
+	match file.strip_prefix(root) {
+		Ok(relative) if !relative.as_os_str().is_empty() => Path::new(given).join(relative).to_string_lossy().to_string(),
+		_ => given.to_string()
+	}
+}
@@ -0,0 +1,123 @@
+//! Matching attachments to the fields they belong to.
+
+use stack_cli_util::{Context, FileResult, Registry, Runner};
+use stack_cli_util::options::{OptionSpec, OptionValues};
+
+const ANSWER_FILES: &str = include_str!("fixtures/answer_files.xml");
+const MISSING_FILES: &str = include_str!("fixtures/missing_files.xml");
+const STACK_QUESTION: &str = include_str!("fixtures/stack_question.xml");
+
+fn runner(actions: &[&str]) -> Runner {
+	runner_with(actions, &[])
+}
+
+/// With the given flag options of the actions set, e.g. ("files", "move").
+fn runner_with(actions: &[&str], flags: &[(&str, &str)]) -> Runner {
+	let mut registry: Registry = Registry::with_builtin_actions().unwrap();
+	for action in registry.iter_mut() {
+		let specs: Vec<OptionSpec> = action.options();
+		let mut values: OptionValues = OptionValues::new();
+		for spec in specs.iter().filter(|s| flags.contains(&(action.flag().as_str(), s.name.as_str()))) {
+			values.set(spec, Vec::new());
+		}
+		values.fill_defaults(&specs);
+		action.configure(&values).unwrap();
+	}
+	let context = Context { write: false, diff: true, file: None, roots: Vec::new() };
+	Runner::new(registry, actions.iter().map(|a| a.to_string()).collect(), context)
+}
+
+/// The messages of the notes of the first question.
+fn messages(result: &FileResult) -> Vec<String> {
+	result.questions[0].notes.iter().map(|n| n.note.message.clone()).collect()
+}
+
+#[test]
+fn answers_keep_their_own_files() {
+	let result: FileResult = runner(&["files"]).process_content("pictures.xml", ANSWER_FILES.to_string()).unwrap();
+	assert!(result.modified.is_none(), "{:?}", messages(&result));
+
+	let result: FileResult = runner(&["exportfiles"]).process_content("pictures.xml", ANSWER_FILES.to_string()).unwrap();
+	let messages: Vec<String> = messages(&result);
+	assert!(messages.iter().any(|m| m.contains("answer[1]/a.png")), "{:?}", messages);
+	assert!(messages.iter().any(|m| m.contains("answer[2]/a.png")), "{:?}", messages);
+}
+
+#[test]
+fn only_names_written_differently_are_fixed() {
+	let mut runner: Runner = runner_with(&["files"], &[("files", "fix-missing")]);
+	let result: FileResult = runner.process_content("missing.xml", MISSING_FILES.to_string()).unwrap();
+	let modified: String = result.modified.clone().unwrap();
+	assert!(modified.contains("@@PLUGINFILE@@/shape.png"), "{}", modified);
+	// Another name spelled alike is only suggested.
+	assert!(modified.contains("@@PLUGINFILE@@/b.png"), "{}", modified);
+	let messages: Vec<String> = messages(&result);
+	assert!(messages.iter().any(|m| m.contains("'/b.png'") && m.contains("'/a.png' (similar name)")), "{:?}", messages);
+}
+
+#[test]
+fn files_with_the_same_place_on_disk_are_not_exported() {
+	// The same name as "a.png" on a case-insensitive file system.
+	let content: String = MISSING_FILES.replace("name=\"shape.png\"", "name=\"A.png\"");
+	let result: FileResult = runner(&["exportfiles"]).process_content("missing.xml", content).unwrap();
+	let messages: Vec<String> = messages(&result);
+	assert!(messages.iter().any(|m| m.contains("Not exporting '/a.png' and '/A.png'")), "{:?}", messages);
+	assert!(!messages.iter().any(|m| m.contains("Could export")), "{:?}", messages);
+}
+
+#[test]
+fn copies_in_fields_of_one_question_are_all_counted() {
+	let content: String = ANSWER_FILES.replace("c3F1YXJl", "dHJpYW5nbGU=");
+	let mut runner: Runner = runner(&["dupfiles"]);
+	runner.process_content("pictures.xml", content).unwrap();
+	let report: String = runner.selected_actions().next().and_then(|a| a.report()).unwrap();
+	assert!(report.contains("2 copies in 1 questions"), "{}", report);
+	assert!(report.contains("answer[1]: '/a.png'") && report.contains("answer[2]: '/a.png'"), "{}", report);
+}
+
+/// The content of the first `<tag>` element.
+fn element<'a>(content: &'a str, tag: &str) -> &'a str {
+	let start: usize = content.find(&format!("<{tag}")).unwrap();
+	let end: usize = content[start..].find(&format!("</{tag}>")).unwrap() + start;
+	&content[start..end]
+}
+
+#[test]
+fn moved_files_leave_the_text_to_other_actions() {
+	const MOVED: &str = "<file name=\"moved.png\" path=\"/\" encoding=\"base64\">bW92ZWQ=</file>";
+	// The question text keeps its own file and gets one from the general feedback.
+	let content: String = STACK_QUESTION
+		.replace("<img src='@@PLUGINFILE@@/single.png'>", "<img src='@@PLUGINFILE@@/single.png'><img src='@@PLUGINFILE@@/moved.png'>")
+		.replace("<text><![CDATA[]]></text>\n    </generalfeedback>", &format!("<text><![CDATA[]]></text>\n      {MOVED}\n    </generalfeedback>"));
+	let result: FileResult = runner_with(&["files", "stacklang"], &[("files", "move")]).process_content("moved.xml", content).unwrap();
+	assert!(result.conflicts.is_empty(), "{:?}", result.conflicts);
+	let modified: String = result.modified.unwrap();
+	let questiontext: &str = element(&modified, "questiontext");
+	assert!(questiontext.contains("[[lang code='en']]Hello[[/lang]]"), "{}", questiontext);
+	assert!(questiontext.contains("<file name=\"single.png\"") && questiontext.contains(MOVED), "{}", questiontext);
+	assert!(!element(&modified, "generalfeedback").contains("moved.png"), "{}", modified);
+
+	// Files swapping fields take each other's places.
+	let content: String = STACK_QUESTION
+		.replace("<img src='@@PLUGINFILE@@/single.png'>", "<img src='@@PLUGINFILE@@/moved.png'>")
+		.replace("<text><![CDATA[]]></text>\n    </generalfeedback>", &format!("<text><![CDATA[<img src=\"@@PLUGINFILE@@/single.png\">]]></text>\n      {MOVED}\n    </generalfeedback>"));
+	let result: FileResult = runner_with(&["files", "stacklang"], &[("files", "move")]).process_content("swapped.xml", content).unwrap();
+	assert!(result.conflicts.is_empty(), "{:?}", result.conflicts);
+	let modified: String = result.modified.unwrap();
+	let questiontext: &str = element(&modified, "questiontext");
+	assert!(questiontext.contains("[[lang code='en']]Hello[[/lang]]"), "{}", questiontext);
+	assert!(questiontext.contains(MOVED) && !questiontext.contains("single.png\" path"), "{}", questiontext);
+	assert!(element(&modified, "generalfeedback").contains("<file name=\"single.png\""), "{}", modified);
+}
+
+#[test]
+fn references_are_fixed_in_the_converted_text() {
+	let content: String = STACK_QUESTION.replace("@@PLUGINFILE@@/single.png", "@@PLUGINFILE@@/Single.PNG");
+	let mut runner: Runner = runner_with(&["files", "stacklang"], &[("files", "fix-missing")]);
+	let result: FileResult = runner.process_content("converted.xml", content).unwrap();
+	assert!(result.conflicts.is_empty(), "{:?}", result.conflicts);
+	let questiontext: String = element(&result.modified.unwrap(), "questiontext").to_string();
+	assert!(questiontext.contains("[[lang code='en']]Hello[[/lang]]"), "{}", questiontext);
+	assert!(questiontext.contains("@@PLUGINFILE@@/single.png"), "{}", questiontext);
+	assert!(!questiontext.contains("Single.PNG"), "{}", questiontext);
+}
@@ -0,0 +1,69 @@
+//! Locating the changes of actions in the document.
+
+use position_preserving_moodle_question_xml_edit::{Change, ContentRef, ContentType, QParser};
+use stack_cli_util::changes::ChangeSet;
+
+const STACK_QUESTION: &str = include_str!("fixtures/stack_question.xml");
+
+fn whole_element(element: &ContentType) -> ContentRef {
+	match element {
+		ContentType::Element(_name, whole_element, _parts) => whole_element.clone(),
+		_ => panic!("Not an element.")
+	}
+}
+
+fn ranges(set: &ChangeSet) -> Vec<(usize, usize)> {
+	set.iter().map(|p| p.range).collect()
+}
+
+#[test]
+fn ranges_match_the_content_they_point_to() {
+	let mut parser: QParser = QParser::from_string(STACK_QUESTION.to_string()).unwrap();
+	// A change made first, so that the parser is not at its first version.
+	let name: ContentRef = parser.find_questions()[0].name.clone();
+	parser.register_change(Change::new(name.clone(), "Renamed".to_string()));
+	parser.execute_changes();
+
+	let elements: Vec<ContentRef> = parser.get_elements(0, vec!["text".to_string(), "file".to_string()])
+		.iter().map(whole_element).collect();
+	let content: String = parser.get_current_content();
+	let mut set: ChangeSet = ChangeSet::new(&mut parser);
+	set.add("test", 1, elements.iter().map(|e| Change::new(e.clone(), String::new())).collect(), &[]);
+	assert_eq!(set.content(), content);
+	assert_eq!(ranges(&set).len(), elements.len());
+	for (range, element) in ranges(&set).iter().zip(&elements) {
+		assert_eq!(&content[range.0..range.1], element.content);
+	}
+	assert!(!set.has_conflicts());
+
+	// Nested ones get located one at a time.
+	let question: ContentRef = parser.find_questions()[0].whole_element.clone();
+	let mut set: ChangeSet = ChangeSet::new(&mut parser);
+	set.add("test", 1, vec![Change::new(question, String::new()), Change::new(elements[0].clone(), String::new())], &[]);
+	let located: Vec<(usize, usize)> = ranges(&set);
+	assert_eq!(located[0], (content.find("<question type=\"stack\"").unwrap(), content.rfind("</question>").unwrap() + "</question>".len()));
+	assert_eq!(&content[located[1].0..located[1].1], elements[0].content);
+	assert_eq!(set.conflicts().len(), 1);
+
+	// One from before the change is taken to cover everything.
+	let mut set: ChangeSet = ChangeSet::new(&mut parser);
+	set.add("test", 1, vec![Change::new(name, String::new())], &[]);
+	assert_eq!(ranges(&set), vec![(0, usize::MAX)]);
+}
+
+#[test]
+fn changes_after_registering_are_located_in_the_new_version() {
+	let mut parser: QParser = QParser::from_string(STACK_QUESTION.to_string()).unwrap();
+	let mut set: ChangeSet = ChangeSet::new(&mut parser);
+	let name: ContentRef = parser.find_questions()[0].name.clone();
+	set.add("test", 1, vec![Change::new(name, "Renamed".to_string())], &[]);
+	set.register(&mut parser).unwrap();
+	assert!(set.is_empty());
+
+	let content: String = parser.get_current_content();
+	assert_eq!(set.content(), content);
+	let name: ContentRef = parser.find_questions()[0].name.clone();
+	set.add("test", 1, vec![Change::new(name, "Again".to_string())], &[]);
+	let range: (usize, usize) = ranges(&set)[0];
+	assert_eq!(&content[range.0..range.1], "Renamed");
+}
@@ -0,0 +1,83 @@
+//! Running the actions over whole files through the library.
+
+use stack_cli_util::{Context, FileResult, Registry, Runner};
+use stack_cli_util::review::{Proposal, Review};
+use std::sync::{Arc, Mutex};
+
+const STACK_QUESTION: &str = include_str!("fixtures/stack_question.xml");
+
+fn runner(actions: &[&str]) -> Runner {
+	let registry: Registry = Registry::with_builtin_actions().unwrap();
+	let context = Context { write: false, diff: true, file: None, roots: Vec::new() };
+	Runner::new(registry, actions.iter().map(|a| a.to_string()).collect(), context)
+}
+
+/// The question with an element removed, like in older exports.
+fn without(element: &str) -> String {
+	let start: usize = STACK_QUESTION.find(&format!("<{element}")).unwrap();
+	let end_tag: String = format!("</{element}>");
+	let end: usize = STACK_QUESTION.find(&end_tag).unwrap() + end_tag.len();
+	format!("{}{}", &STACK_QUESTION[..start], &STACK_QUESTION[end..])
+}
+
+#[test]
+fn question_missing_an_element_is_skipped_not_fatal() {
+	let mut runner: Runner = runner(&["stacklang", "stackextract"]);
+	let broken: FileResult = runner.process_content("broken.xml", without("questiondescription")).unwrap();
+	let (action, reason) = broken.questions[0].failure.clone().unwrap();
+	assert_eq!(action, "stacklang");
+	assert!(reason.contains("questiondescription"), "{}", reason);
+	assert!(broken.modified.is_none());
+
+	// The same runner carries on with the next file.
+	let good: FileResult = runner.process_content("good.xml", STACK_QUESTION.to_string()).unwrap();
+	assert!(good.questions[0].failure.is_none());
+	assert!(good.modified.is_some_and(|m| m.contains("[[lang code='en']]")));
+}
+
+/// Records the fields of each proposal and declines those changing
+/// `declined`.
+struct Recording {
+	proposals: Arc<Mutex<Vec<Vec<String>>>>,
+	declined: &'static str
+}
+
+impl Review for Recording {
+	fn review(&mut self, proposal: &Proposal) -> bool {
+		let fields: Vec<String> = proposal.changes.iter().map(|c| c.field.clone()).collect();
+		let made: bool = !fields.iter().any(|f| f == self.declined);
+		self.proposals.lock().unwrap().push(fields);
+		made
+	}
+}
+
+#[test]
+fn changes_are_reviewed_one_by_one_unless_linked() {
+	// A multiple choice input with localised labels, which get moved to
+	// the question variables.
+	let question: String = STACK_QUESTION
+		.replace("<type>algebraic</type>", "<type>radio</type>")
+		.replace("<tans>ta</tans>\n      <boxsize>", "<tans>[[1,true,\"{mlang en}One{mlang}\"],[2,false,\"{mlang en}Two{mlang}\"]]</tans>\n      <boxsize>")
+		.replace("<text>Correct answer, well done.</text>", "<text>{mlang en}Well done.{mlang}</text>");
+	let proposals: Arc<Mutex<Vec<Vec<String>>>> = Arc::new(Mutex::new(Vec::new()));
+	let mut runner: Runner = runner(&["stacklang"]).with_review(Box::new(Recording {
+		proposals: proposals.clone(),
+		declined: "questiontext/text"
+	}));
+	let result: FileResult = runner.process_content("mcq.xml", question).unwrap();
+
+	let mut proposals: Vec<Vec<String>> = proposals.lock().unwrap().clone();
+	proposals.sort();
+	assert_eq!(proposals, vec![
+		vec!["input[ans1]/tans".to_string(), "questionvariables/text".to_string()],
+		vec!["prtcorrect/text".to_string()],
+		vec!["questiontext/text".to_string()]
+	]);
+	let modified: String = result.modified.unwrap();
+	assert!(modified.contains("<tans>auto_relocated_ans1_options</tans>"));
+	assert!(modified.contains("auto_relocated_ans1_options: [[1,true,castext("));
+	assert!(modified.contains("[[lang code='en']]Well done.[[/lang]]"));
+	assert!(modified.contains("{mlang en}Hello{mlang}"));
+	let declined: Vec<String> = result.questions[0].notes.iter().filter(|n| n.note.kind.as_deref() == Some("declined")).map(|n| n.note.message.clone()).collect();
+	assert_eq!(declined, vec!["Left out 1 changes on review."]);
+}